@@ -0,0 +1,19 @@
+-- The schema the crawler was first written against. Uses IF NOT EXISTS so it
+-- also adopts a database that predates `schema_version`.
+CREATE TABLE IF NOT EXISTS voxes (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    author VARCHAR(255) NOT NULL,
+    log_id VARCHAR(255) NOT NULL,
+    date DATE NOT NULL,
+    content TEXT NOT NULL,
+    INDEX voxes_log_id (log_id)
+) CHARACTER SET utf8mb4;
+
+CREATE TABLE IF NOT EXISTS vox_meta (
+    id BIGINT UNSIGNED NOT NULL PRIMARY KEY,
+    indexed_content TEXT NOT NULL,
+    has_song BOOLEAN NOT NULL DEFAULT FALSE,
+    has_morshu BOOLEAN NOT NULL DEFAULT FALSE,
+    has_grant BOOLEAN NOT NULL DEFAULT FALSE,
+    FULLTEXT INDEX vox_meta_indexed_content (indexed_content)
+) CHARACTER SET utf8mb4;
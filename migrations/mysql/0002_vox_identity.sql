@@ -0,0 +1,15 @@
+-- Gives every vox a stable identity of (log_id, position) plus a hash of its
+-- content, so re-importing a log can tell new, unchanged and changed voxes apart.
+ALTER TABLE voxes
+    ADD COLUMN position INT UNSIGNED NULL,
+    ADD COLUMN content_hash CHAR(64) NULL;
+
+-- Rows from before this migration are numbered in insertion order within their log
+UPDATE voxes
+JOIN (SELECT id, ROW_NUMBER() OVER (PARTITION BY log_id ORDER BY id) - 1 AS position FROM voxes) AS numbered
+    ON voxes.id = numbered.id
+SET voxes.position = numbered.position, voxes.content_hash = SHA2(voxes.content, 256);
+
+ALTER TABLE voxes MODIFY COLUMN position INT UNSIGNED NOT NULL;
+
+CREATE UNIQUE INDEX voxes_identity ON voxes (log_id, position);
@@ -0,0 +1,10 @@
+-- What each log looked like the last time it was crawled, so a pull can send
+-- conditional requests and only re-ingest logs whose content changed.
+CREATE TABLE IF NOT EXISTS vox_logs (
+    log_id VARCHAR(255) NOT NULL PRIMARY KEY,
+    size BIGINT UNSIGNED NOT NULL,
+    etag VARCHAR(255) NULL,
+    last_modified VARCHAR(64) NULL,
+    content_hash CHAR(64) NOT NULL,
+    crawled_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+) CHARACTER SET utf8mb4;
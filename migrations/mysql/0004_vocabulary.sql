@@ -0,0 +1,8 @@
+-- The word list voxes are validated against, for `--vocab db`, and which
+-- version of it each `vox_meta` row was indexed with. Rows indexed before
+-- this have no version, and count as stale.
+CREATE TABLE IF NOT EXISTS vocabulary (
+    word VARCHAR(255) NOT NULL PRIMARY KEY
+) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin;
+
+ALTER TABLE vox_meta ADD COLUMN vocab_version VARCHAR(64) NULL;
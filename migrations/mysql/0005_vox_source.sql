@@ -0,0 +1,3 @@
+-- Which log source (see `source::open`) each vox was pulled from. Rows pulled
+-- before this have none, and get one the next time their log is pulled.
+ALTER TABLE voxes ADD COLUMN source VARCHAR(1024) NULL;
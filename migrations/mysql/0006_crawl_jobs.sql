@@ -0,0 +1,24 @@
+-- Pulls and reindexes, and how far each got through its listings, so `resume`
+-- can pick up a run that died where it stopped. `command` is the run's options
+-- as JSON, `source` what it was listing.
+CREATE TABLE IF NOT EXISTS crawl_jobs (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    command TEXT NOT NULL,
+    source VARCHAR(1024) NOT NULL,
+    started_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at TIMESTAMP NULL
+) CHARACTER SET utf8mb4;
+
+CREATE TABLE IF NOT EXISTS crawl_job_listings (
+    job_id BIGINT UNSIGNED NOT NULL,
+    position INT UNSIGNED NOT NULL,
+    log_id VARCHAR(255) NOT NULL,
+    date DATE NOT NULL,
+    -- pending, fetched, committed, indexed or skipped, how far the listing got
+    status VARCHAR(16) NOT NULL,
+    -- Why the last attempt failed, the status stays where it got to
+    error TEXT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (job_id, position),
+    INDEX crawl_job_listings_log_id (job_id, log_id)
+) CHARACTER SET utf8mb4;
@@ -0,0 +1,30 @@
+-- Same tables as migrations/mysql/0001_initial.sql. `vox_meta_fts` mirrors
+-- `vox_meta.indexed_content` through triggers, standing in for MySQL's FULLTEXT index.
+CREATE TABLE IF NOT EXISTS voxes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    author TEXT NOT NULL,
+    log_id TEXT NOT NULL,
+    date TEXT NOT NULL,
+    content TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS voxes_log_id ON voxes (log_id);
+
+CREATE TABLE IF NOT EXISTS vox_meta (
+    id INTEGER PRIMARY KEY,
+    indexed_content TEXT NOT NULL,
+    has_song INTEGER NOT NULL DEFAULT 0,
+    has_morshu INTEGER NOT NULL DEFAULT 0,
+    has_grant INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS vox_meta_fts USING fts5 (indexed_content, content = 'vox_meta', content_rowid = 'id');
+CREATE TRIGGER IF NOT EXISTS vox_meta_ai AFTER INSERT ON vox_meta BEGIN
+    INSERT INTO vox_meta_fts (rowid, indexed_content) VALUES (new.id, new.indexed_content);
+END;
+CREATE TRIGGER IF NOT EXISTS vox_meta_ad AFTER DELETE ON vox_meta BEGIN
+    INSERT INTO vox_meta_fts (vox_meta_fts, rowid, indexed_content) VALUES ('delete', old.id, old.indexed_content);
+END;
+CREATE TRIGGER IF NOT EXISTS vox_meta_au AFTER UPDATE ON vox_meta BEGIN
+    INSERT INTO vox_meta_fts (vox_meta_fts, rowid, indexed_content) VALUES ('delete', old.id, old.indexed_content);
+    INSERT INTO vox_meta_fts (rowid, indexed_content) VALUES (new.id, new.indexed_content);
+END;
@@ -0,0 +1,8 @@
+-- Same as migrations/mysql/0002_vox_identity.sql. SQLite has no SHA2, so rows
+-- from before this migration get their content_hash on their next import.
+ALTER TABLE voxes ADD COLUMN position INTEGER;
+ALTER TABLE voxes ADD COLUMN content_hash TEXT;
+
+UPDATE voxes SET position = (SELECT COUNT(*) FROM voxes AS earlier WHERE earlier.log_id = voxes.log_id AND earlier.id < voxes.id);
+
+CREATE UNIQUE INDEX voxes_identity ON voxes (log_id, position);
@@ -0,0 +1,9 @@
+-- Same as migrations/mysql/0003_log_state.sql
+CREATE TABLE IF NOT EXISTS vox_logs (
+    log_id TEXT NOT NULL PRIMARY KEY,
+    size INTEGER NOT NULL,
+    etag TEXT,
+    last_modified TEXT,
+    content_hash TEXT NOT NULL,
+    crawled_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,6 @@
+-- Same as migrations/mysql/0004_vocabulary.sql
+CREATE TABLE IF NOT EXISTS vocabulary (
+    word TEXT NOT NULL PRIMARY KEY
+);
+
+ALTER TABLE vox_meta ADD COLUMN vocab_version TEXT;
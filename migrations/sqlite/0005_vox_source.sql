@@ -0,0 +1,2 @@
+-- Same as migrations/mysql/0005_vox_source.sql
+ALTER TABLE voxes ADD COLUMN source TEXT;
@@ -0,0 +1,22 @@
+-- Same as migrations/mysql/0006_crawl_jobs.sql
+CREATE TABLE IF NOT EXISTS crawl_jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    command TEXT NOT NULL,
+    source TEXT NOT NULL,
+    started_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS crawl_job_listings (
+    job_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    log_id TEXT NOT NULL,
+    date TEXT NOT NULL,
+    -- pending, fetched, committed, indexed or skipped, how far the listing got
+    status TEXT NOT NULL,
+    -- Why the last attempt failed, the status stays where it got to
+    error TEXT,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (job_id, position)
+);
+CREATE INDEX IF NOT EXISTS crawl_job_listings_log_id ON crawl_job_listings (job_id, log_id);
@@ -0,0 +1,408 @@
+// Where the crawler reads from and writes to. Each setting is resolved from, last one winning:
+//
+//   1. the built-in defaults, which are the production setup
+//   2. a profile of the config file (`--config`, VOXCRAWLER_CONFIG, or ./voxcrawler.toml if it's there)
+//   3. VOXCRAWLER_* env vars
+//   4. command line flags
+//
+// The profile is picked by `--profile`, VOXCRAWLER_PROFILE, the file's `default_profile`, or `prod`.
+// See voxcrawler.example.toml for a file with prod, staging and local profiles.
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::error::{VoxError, VoxResult};
+use crate::http::HttpSettings;
+use crate::logging::{self, LogFormat};
+use crate::report::ReportFormat;
+
+const DEFAULT_CONFIG_PATH: &str = "voxcrawler.toml";
+const DEFAULT_PROFILE: &str = "prod";
+const PROD_DB_HOST: &str = "vox.belbeeno.com/voxsearch";
+const DEFAULT_SOURCE: &str = "https://rook.zone/voxlogs";
+const DEFAULT_REPORT_DIR: &str = "logs";
+const DEFAULT_DRY_RUN_LOG: &str = "dry_run.txt";
+const DEFAULT_CONCURRENCY: usize = 4;
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    default_profile: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    // A full database URL, see `store::open`
+    db: Option<String>,
+    // `host/database` of a MySQL DB, logged into with VOXCRAWLER_USER/VOXCRAWLER_PASS
+    db_host: Option<String>,
+    // Where logs are pulled from, see `source::open`. Older files call it `listing_url`.
+    #[serde(alias = "listing_url")]
+    source: Option<String>,
+    report_dir: Option<PathBuf>,
+    // `text`, `jsonl` or `csv`
+    report_format: Option<ReportFormat>,
+    dry_run_log: Option<PathBuf>,
+    // A level or filter directives, see `logging::filter`
+    log_level: Option<String>,
+    // `text` or `json`
+    log_format: Option<LogFormat>,
+    // See `Vocabulary::load`
+    vocab: Option<String>,
+    // How many logs `pull` and `reindex` work on at once
+    concurrency: Option<usize>,
+    #[serde(default)]
+    http: HttpProfile,
+}
+
+// The `[profiles.<name>.http]` table, see `HttpSettings`
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+struct HttpProfile {
+    connect_timeout_secs: Option<u64>,
+    timeout_secs: Option<u64>,
+    retries: Option<u32>,
+    backoff_ms: Option<u64>,
+    requests_per_second: Option<f64>,
+    user_agent: Option<String>,
+}
+
+impl HttpProfile {
+    fn merge(self, other:HttpProfile) -> HttpProfile {
+        HttpProfile {
+            connect_timeout_secs: other.connect_timeout_secs.or(self.connect_timeout_secs),
+            timeout_secs: other.timeout_secs.or(self.timeout_secs),
+            retries: other.retries.or(self.retries),
+            backoff_ms: other.backoff_ms.or(self.backoff_ms),
+            requests_per_second: other.requests_per_second.or(self.requests_per_second),
+            user_agent: other.user_agent.or(self.user_agent),
+        }
+    }
+
+    fn settings(self) -> HttpSettings {
+        let defaults = HttpSettings::default();
+        HttpSettings {
+            connect_timeout: self.connect_timeout_secs.map_or(defaults.connect_timeout, Duration::from_secs),
+            timeout: self.timeout_secs.map_or(defaults.timeout, Duration::from_secs),
+            retries: self.retries.unwrap_or(defaults.retries),
+            backoff: self.backoff_ms.map_or(defaults.backoff, Duration::from_millis),
+            requests_per_second: self.requests_per_second.unwrap_or(defaults.requests_per_second),
+            user_agent: self.user_agent.unwrap_or(defaults.user_agent),
+        }
+    }
+}
+
+impl Profile {
+    // Only prod works out of the box, anything else has to say where its DB is
+    fn builtin(name:&str) -> Profile {
+        Profile {
+            db_host: (name == DEFAULT_PROFILE).then(|| PROD_DB_HOST.to_string()),
+            source: Some(DEFAULT_SOURCE.to_string()),
+            report_dir: Some(PathBuf::from(DEFAULT_REPORT_DIR)),
+            dry_run_log: Some(PathBuf::from(DEFAULT_DRY_RUN_LOG)),
+            log_level: Some(logging::DEFAULT_LEVEL.to_string()),
+            concurrency: Some(DEFAULT_CONCURRENCY),
+            ..Profile::default()
+        }
+    }
+
+    // Settings from `other` win over these
+    fn merge(self, other:Profile) -> Profile {
+        // A URL or a host given later replaces both
+        let (db, db_host) = if other.db.is_some() || other.db_host.is_some() { (other.db, other.db_host) } else { (self.db, self.db_host) };
+        Profile {
+            db,
+            db_host,
+            source: other.source.or(self.source),
+            report_dir: other.report_dir.or(self.report_dir),
+            report_format: other.report_format.or(self.report_format),
+            dry_run_log: other.dry_run_log.or(self.dry_run_log),
+            log_level: other.log_level.or(self.log_level),
+            log_format: other.log_format.or(self.log_format),
+            vocab: other.vocab.or(self.vocab),
+            concurrency: other.concurrency.or(self.concurrency),
+            http: self.http.merge(other.http),
+        }
+    }
+}
+
+// What the command line can set
+#[derive(Default)]
+pub struct Overrides {
+    pub config: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub db: Option<String>,
+    pub vocab: Option<String>,
+    pub source: Option<String>,
+    pub report_dir: Option<PathBuf>,
+    pub report_format: Option<ReportFormat>,
+    pub log_level: Option<String>,
+    pub log_format: Option<LogFormat>,
+    pub concurrency: Option<usize>,
+    pub timeout_secs: Option<u64>,
+    pub retries: Option<u32>,
+    pub requests_per_second: Option<f64>,
+    pub user_agent: Option<String>,
+}
+
+pub struct Config {
+    pub profile: String,
+    // The file the profile came from, if any
+    pub file: Option<PathBuf>,
+    db: Option<String>,
+    db_host: Option<String>,
+    user: Option<String>,
+    pass: Option<String>,
+    pub source: String,
+    pub report_dir: PathBuf,
+    pub report_format: ReportFormat,
+    pub dry_run_log: PathBuf,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub vocab: Option<String>,
+    pub concurrency: usize,
+    pub http: HttpSettings,
+}
+
+// A number from env var `key`, `expected` says what it should be when it isn't one
+fn env_number<T:FromStr>(key:&str, value:Option<String>, expected:&str) -> VoxResult<Option<T>> {
+    value.map(|value| value.parse().map_err(|_| VoxError::Config(format!("{key} should be {expected}, got [{value}]")))).transpose()
+}
+
+impl Config {
+    pub fn load(overrides:Overrides) -> VoxResult<Config> {
+        let env = |key:&str| std::env::var(key).ok().filter(|value| !value.is_empty());
+        let path = overrides.config.clone().or_else(|| env("VOXCRAWLER_CONFIG").map(PathBuf::from));
+        let file = match path {
+            Some(path) => {
+                let text = fs::read_to_string(&path).map_err(|e| VoxError::file(&path, e))?;
+                Some((path, text))
+            },
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+                let text = fs::read_to_string(&path).map_err(|e| VoxError::file(&path, e))?;
+                Some((path, text))
+            },
+            None => None,
+        };
+        Config::resolve(file, overrides, env)
+    }
+
+    // `file` is the config file's path and contents, `env` looks up env vars
+    fn resolve(file:Option<(PathBuf, String)>, overrides:Overrides, env:impl Fn(&str) -> Option<String>) -> VoxResult<Config> {
+        let parsed = match &file {
+            Some((path, text)) => toml::from_str::<ConfigFile>(text)
+                .map_err(|e| VoxError::Config(format!("[{}] isn't a valid config file: {e}", path.display())))?,
+            None => ConfigFile::default(),
+        };
+        let profile = overrides.profile.clone()
+            .or_else(|| env("VOXCRAWLER_PROFILE"))
+            .or(parsed.default_profile)
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+        let from_file = match parsed.profiles.get(&profile) {
+            Some(settings) => settings.clone(),
+            None if profile == DEFAULT_PROFILE => Profile::default(),
+            None => {
+                let known = parsed.profiles.keys().map(String::as_str).collect::<Vec<_>>().join(", ");
+                return Err(VoxError::Config(match &file {
+                    Some((path, _)) => format!("there's no profile [{profile}] in [{}], it has [{known}]", path.display()),
+                    None => format!("profile [{profile}] needs a config file, pass --config or put one at ./{DEFAULT_CONFIG_PATH}"),
+                }));
+            },
+        };
+        let from_env = Profile {
+            db: env("VOXCRAWLER_DB"),
+            source: env("VOXCRAWLER_SOURCE").or_else(|| env("VOXCRAWLER_LISTING_URL")),
+            report_dir: env("VOXCRAWLER_REPORT_DIR").map(PathBuf::from),
+            report_format: env("VOXCRAWLER_REPORT_FORMAT").map(|value| ReportFormat::from_str(&value, true)
+                .map_err(|_| VoxError::Config(format!("VOXCRAWLER_REPORT_FORMAT should be text, jsonl or csv, got [{value}]")))).transpose()?,
+            log_level: env("VOXCRAWLER_LOG_LEVEL"),
+            log_format: env("VOXCRAWLER_LOG_FORMAT").map(|value| LogFormat::from_str(&value, true)
+                .map_err(|_| VoxError::Config(format!("VOXCRAWLER_LOG_FORMAT should be text or json, got [{value}]")))).transpose()?,
+            vocab: env("VOXCRAWLER_VOCAB"),
+            concurrency: env_number("VOXCRAWLER_CONCURRENCY", env("VOXCRAWLER_CONCURRENCY"), "a number")?,
+            http: HttpProfile {
+                timeout_secs: env_number("VOXCRAWLER_TIMEOUT", env("VOXCRAWLER_TIMEOUT"), "a number of seconds")?,
+                retries: env_number("VOXCRAWLER_RETRIES", env("VOXCRAWLER_RETRIES"), "a number")?,
+                requests_per_second: env_number::<f64>("VOXCRAWLER_RATE_LIMIT", env("VOXCRAWLER_RATE_LIMIT"), "requests per second, 0 for no limit")?
+                    .map(|rate| if rate.is_finite() && rate >= 0.0 { Ok(rate) } else {
+                        Err(VoxError::Config(format!("VOXCRAWLER_RATE_LIMIT should be requests per second, 0 for no limit, got [{rate}]")))
+                    }).transpose()?,
+                user_agent: env("VOXCRAWLER_USER_AGENT"),
+                ..HttpProfile::default()
+            },
+            ..Profile::default()
+        };
+        let from_flags = Profile {
+            db: overrides.db,
+            source: overrides.source,
+            report_dir: overrides.report_dir,
+            report_format: overrides.report_format,
+            log_level: overrides.log_level,
+            log_format: overrides.log_format,
+            vocab: overrides.vocab,
+            concurrency: overrides.concurrency,
+            http: HttpProfile {
+                timeout_secs: overrides.timeout_secs,
+                retries: overrides.retries,
+                requests_per_second: overrides.requests_per_second,
+                user_agent: overrides.user_agent,
+                ..HttpProfile::default()
+            },
+            ..Profile::default()
+        };
+        let settings = Profile::builtin(&profile).merge(from_file).merge(from_env).merge(from_flags);
+        if settings.concurrency == Some(0) {
+            return Err(VoxError::Config(format!("profile [{profile}] has a concurrency of 0, it needs at least one worker")));
+        }
+        let log_level = settings.log_level.unwrap_or_default();
+        logging::filter(&log_level)?;
+        Ok(Config {
+            file: file.map(|(path, _)| path),
+            db: settings.db,
+            db_host: settings.db_host,
+            user: env("VOXCRAWLER_USER"),
+            pass: env("VOXCRAWLER_PASS"),
+            source: settings.source.unwrap_or_default(),
+            report_dir: settings.report_dir.unwrap_or_default(),
+            report_format: settings.report_format.unwrap_or_default(),
+            dry_run_log: settings.dry_run_log.unwrap_or_default(),
+            log_level,
+            log_format: settings.log_format.unwrap_or_default(),
+            vocab: settings.vocab,
+            concurrency: settings.concurrency.unwrap_or(DEFAULT_CONCURRENCY),
+            http: settings.http.settings(),
+            profile,
+        })
+    }
+
+    // Only asked for by commands that use the DB, so the rest work without one
+    pub fn db_url(&self) -> VoxResult<String> {
+        if let Some(url) = &self.db {
+            return Ok(url.clone());
+        }
+        let Some(host) = &self.db_host else {
+            let file = self.file.as_ref().map_or(DEFAULT_CONFIG_PATH.to_string(), |path| path.display().to_string());
+            return Err(VoxError::Config(format!(
+                "profile [{}] has no database, set `db` or `db_host` for it in [{file}], VOXCRAWLER_DB, or pass --db", self.profile)));
+        };
+        let missing = |var:&str| VoxError::Config(format!("profile [{}] logs into [{host}] with {var}, which isn't set", self.profile));
+        let user = self.user.as_ref().ok_or_else(|| missing("VOXCRAWLER_USER"))?;
+        let pass = self.pass.as_ref().ok_or_else(|| missing("VOXCRAWLER_PASS"))?;
+        Ok(format!("mysql://{user}:{pass}@{host}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const FILE: &str = r#"
+default_profile = "local"
+
+[profiles.prod]
+vocab = "db"
+
+[profiles.staging]
+db_host = "staging.example.com/voxsearch"
+listing_url = "https://staging.example.com/voxlogs/"
+
+[profiles.staging.http]
+retries = 0
+user_agent = "voxcrawler-staging"
+
+[profiles.local]
+db = "sqlite://vox.db"
+source = "backups/voxlogs.tar.gz"
+concurrency = 1
+report_dir = "local_logs"
+report_format = "csv"
+log_level = "debug,filters=trace"
+"#;
+
+    fn resolve(file:Option<&str>, overrides:Overrides, env:&[(&str, &str)]) -> VoxResult<Config> {
+        let env : HashMap<String, String> = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Config::resolve(file.map(|text| (PathBuf::from("test.toml"), text.to_string())), overrides, |key| env.get(key).cloned())
+    }
+
+    #[test]
+    fn defaults_are_prod() {
+        let config = resolve(None, Overrides::default(), &[("VOXCRAWLER_USER", "u"), ("VOXCRAWLER_PASS", "p")]).unwrap();
+        assert_eq!(config.db_url().unwrap(), format!("mysql://u:p@{PROD_DB_HOST}"));
+        assert_eq!(config.source, DEFAULT_SOURCE);
+        assert_eq!(config.report_dir, PathBuf::from("logs"));
+        assert!(config.vocab.is_none());
+        assert_eq!(config.concurrency, DEFAULT_CONCURRENCY);
+        assert_eq!(config.report_format, ReportFormat::Text);
+        assert_eq!((config.log_level.as_str(), config.log_format), ("info", LogFormat::Text));
+    }
+
+    #[test]
+    fn profiles_then_env_then_flags() {
+        let local = resolve(Some(FILE), Overrides::default(), &[]).unwrap();
+        assert_eq!(local.profile, "local");
+        assert_eq!(local.db_url().unwrap(), "sqlite://vox.db");
+        assert_eq!(local.report_dir, PathBuf::from("local_logs"));
+        assert_eq!(local.source, "backups/voxlogs.tar.gz");
+        assert_eq!(local.concurrency, 1);
+        assert_eq!(local.report_format, ReportFormat::Csv);
+        assert_eq!(local.log_level, "debug,filters=trace");
+
+        let staging = resolve(Some(FILE), Overrides::default(), &[("VOXCRAWLER_PROFILE", "staging"), ("VOXCRAWLER_USER", "u"), ("VOXCRAWLER_PASS", "p")]).unwrap();
+        assert_eq!(staging.db_url().unwrap(), "mysql://u:p@staging.example.com/voxsearch");
+        assert_eq!(staging.source, "https://staging.example.com/voxlogs/");
+        assert_eq!((staging.http.retries, staging.http.user_agent.as_str()), (0, "voxcrawler-staging"));
+        assert_eq!(staging.http.timeout, HttpSettings::default().timeout);
+
+        let prod = resolve(Some(FILE), Overrides { profile: Some("prod".to_string()), ..Overrides::default() }, &[]).unwrap();
+        assert_eq!(prod.vocab.as_deref(), Some("db"));
+
+        let overridden = resolve(Some(FILE), Overrides { db: Some("sqlite://flag.db".to_string()), ..Overrides::default() },
+            &[("VOXCRAWLER_DB", "sqlite://env.db"), ("VOXCRAWLER_REPORT_DIR", "env_logs"), ("VOXCRAWLER_REPORT_FORMAT", "JSONL"), ("VOXCRAWLER_LOG_FORMAT", "json")]).unwrap();
+        assert_eq!(overridden.db_url().unwrap(), "sqlite://flag.db");
+        assert_eq!(overridden.report_dir, PathBuf::from("env_logs"));
+        assert_eq!(overridden.report_format, ReportFormat::Jsonl);
+        assert_eq!(overridden.log_format, LogFormat::Json);
+
+        let http_env = [("VOXCRAWLER_TIMEOUT", "5"), ("VOXCRAWLER_RETRIES", "1"), ("VOXCRAWLER_RATE_LIMIT", "0.5"), ("VOXCRAWLER_USER_AGENT", "voxcrawler-env")];
+        let from_env = resolve(Some(FILE), Overrides { profile: Some("staging".to_string()), ..Overrides::default() }, &http_env).unwrap();
+        assert_eq!(from_env.http.timeout, Duration::from_secs(5));
+        assert_eq!((from_env.http.retries, from_env.http.requests_per_second, from_env.http.user_agent.as_str()), (1, 0.5, "voxcrawler-env"));
+
+        let flagged = resolve(Some(FILE), Overrides { profile: Some("staging".to_string()), retries: Some(5), ..Overrides::default() }, &http_env).unwrap();
+        assert_eq!((flagged.http.retries, flagged.http.user_agent.as_str()), (5, "voxcrawler-env"));
+    }
+
+    #[test]
+    fn missing_settings_say_what_to_set() {
+        let prod = resolve(None, Overrides::default(), &[("VOXCRAWLER_USER", "u")]).unwrap();
+        assert!(prod.db_url().unwrap_err().to_string().contains("VOXCRAWLER_PASS"));
+
+        let unknown = resolve(Some(FILE), Overrides { profile: Some("qa".to_string()), ..Overrides::default() }, &[]);
+        assert!(unknown.err().unwrap().to_string().contains("[local, prod, staging]"));
+        assert!(resolve(None, Overrides { profile: Some("local".to_string()), ..Overrides::default() }, &[]).is_err());
+        assert!(resolve(Some("[profiles.local]\ndatabase = \"x\""), Overrides::default(), &[]).is_err());
+        assert!(resolve(None, Overrides::default(), &[("VOXCRAWLER_CONCURRENCY", "lots")]).is_err());
+        assert!(resolve(None, Overrides::default(), &[("VOXCRAWLER_TIMEOUT", "1m")]).err().unwrap().to_string().contains("VOXCRAWLER_TIMEOUT"));
+        assert!(resolve(None, Overrides::default(), &[("VOXCRAWLER_RETRIES", "-1")]).is_err());
+        assert!(resolve(None, Overrides::default(), &[("VOXCRAWLER_RATE_LIMIT", "fast")]).is_err());
+        assert!(resolve(None, Overrides::default(), &[("VOXCRAWLER_RATE_LIMIT", "-2")]).is_err());
+        assert!(resolve(None, Overrides::default(), &[("VOXCRAWLER_RATE_LIMIT", "NaN")]).is_err());
+        assert!(resolve(None, Overrides::default(), &[("VOXCRAWLER_REPORT_FORMAT", "xml")]).is_err());
+        assert!(resolve(None, Overrides { log_level: Some("filters=loud".to_string()), ..Overrides::default() }, &[]).is_err());
+        assert!(resolve(None, Overrides::default(), &[("VOXCRAWLER_LOG_FORMAT", "xml")]).is_err());
+        assert!(resolve(None, Overrides { concurrency: Some(0), ..Overrides::default() }, &[]).is_err());
+
+        let no_db = resolve(Some("[profiles.local]\nvocab = \"db\""), Overrides { profile: Some("local".to_string()), ..Overrides::default() }, &[]).unwrap();
+        assert!(no_db.db_url().unwrap_err().to_string().contains("VOXCRAWLER_DB"));
+    }
+}
@@ -0,0 +1,179 @@
+// What committing and indexing a log would change, for dry runs to show without writing anything.
+//
+// `commit` diffs the voxes, matched to the stored ones by position as `upsert_voxes` does, and
+// `index_log` then diffs the `vox_meta` rows it built from them. Only the words and flags count as
+// a change there, a row that would only get the new vocab version isn't listed.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::store::{IngestReport, VoxEntry, VoxIndexData};
+
+// A vox the log has at a position where `voxes` has something else
+pub struct ChangedVox {
+    pub old: VoxEntry,
+    pub new: VoxEntry,
+}
+
+pub struct VoxDiff {
+    pub log_id: String,
+    // Nothing stored at their position yet
+    pub inserted: Vec<VoxEntry>,
+    pub changed: Vec<ChangedVox>,
+    pub unchanged: usize,
+    // Stored past the end of the log, `truncate_log` would delete them and their `vox_meta` rows
+    pub orphaned: Vec<VoxEntry>,
+}
+
+// `voxes` is the log as it would be committed, `stored` what `voxes_for_log` has for it now.
+// Voxes that would update a stored row get its id, as they'd keep it.
+pub fn diff_voxes(log_id:&str, voxes:&mut [VoxEntry], stored:Vec<VoxEntry>) -> VoxDiff {
+    let mut stored : HashMap<u32, VoxEntry> = stored.into_iter().map(|vox| (vox.position, vox)).collect();
+    let mut diff = VoxDiff { log_id: log_id.to_string(), inserted: Vec::new(), changed: Vec::new(), unchanged: 0, orphaned: Vec::new() };
+    for vox in voxes.iter_mut() {
+        match stored.remove(&vox.position) {
+            None => diff.inserted.push(vox.clone()),
+            Some(old) => {
+                vox.id = old.id;
+                if old.content_hash == vox.content_hash && old.author == vox.author {
+                    diff.unchanged += 1;
+                }
+                else {
+                    diff.changed.push(ChangedVox { old, new: vox.clone() });
+                }
+            },
+        }
+    }
+    diff.orphaned = stored.into_values().collect();
+    diff.orphaned.sort_by_key(|vox| vox.position);
+    diff
+}
+
+impl VoxDiff {
+    // What committing the log would report
+    pub fn ingest(&self) -> IngestReport {
+        IngestReport { new: self.inserted.len(), unchanged: self.unchanged, changed: self.changed.len(), removed: self.orphaned.len() }
+    }
+}
+
+// One line per vox, `+` inserted, `~` changed, `-` orphaned
+impl fmt::Display for VoxDiff {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "=== Vox diff for [{}] - {} ===", self.log_id, self.ingest())?;
+        for vox in &self.inserted {
+            write!(f, "\n+ [{}] {}: {:?}", vox.position, vox.author, vox.content)?;
+        }
+        for ChangedVox { old, new } in &self.changed {
+            write!(f, "\n~ [{}] vox [{}] {}: {:?} -> {}: {:?}", new.position, old.id, old.author, old.content, new.author, new.content)?;
+        }
+        for vox in &self.orphaned {
+            write!(f, "\n- [{}] vox [{}] {}: {:?}", vox.position, vox.id, vox.author, vox.content)?;
+        }
+        Ok(())
+    }
+}
+
+// A `vox_meta` row that would be written with other words or flags, or that isn't there yet
+pub struct IndexChange {
+    pub position: u32,
+    pub old: Option<VoxIndexData>,
+    pub new: VoxIndexData,
+}
+
+pub struct IndexDiff {
+    pub log_id: String,
+    // How many rows would be written in all
+    pub rows: usize,
+    pub changes: Vec<IndexChange>,
+}
+
+// `rows` are the `vox_meta` rows built for `voxes`, in the same order, and `stored` what
+// `index_for_log` has now. Voxes that would be inserted have no id yet, so no row to compare with.
+pub fn diff_index(log_id:&str, voxes:&[VoxEntry], rows:Vec<VoxIndexData>, stored:Vec<VoxIndexData>) -> IndexDiff {
+    let mut stored : HashMap<u64, VoxIndexData> = stored.into_iter().map(|row| (row.id, row)).collect();
+    let mut diff = IndexDiff { log_id: log_id.to_string(), rows: rows.len(), changes: Vec::new() };
+    for (vox, new) in voxes.iter().zip(rows) {
+        let old = if vox.id == 0 { None } else { stored.remove(&vox.id) };
+        if !old.as_ref().is_some_and(|old| same_index(old, &new)) {
+            diff.changes.push(IndexChange { position: vox.position, old, new });
+        }
+    }
+    diff
+}
+
+fn same_index(old:&VoxIndexData, new:&VoxIndexData) -> bool {
+    old.indexed_content == new.indexed_content && (old.has_song, old.has_morshu, old.has_grant) == (new.has_song, new.has_morshu, new.has_grant)
+}
+
+// One line per row that would change
+impl fmt::Display for IndexDiff {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "=== Index diff for [{}] - [{}] row(s) written, [{}] changed ===", self.log_id, self.rows, self.changes.len())?;
+        let flags = |row:&VoxIndexData| format!("SONG:[{}] MORSHU:[{}] GRANT:[{}]", row.has_song, row.has_morshu, row.has_grant);
+        for IndexChange { position, old, new } in &self.changes {
+            match old {
+                None => write!(f, "\n# [{position}] new row {} CONTENT:[{}]", flags(new), new.indexed_content.trim_end())?,
+                Some(old) => {
+                    write!(f, "\n# [{position}] vox [{}]", old.id)?;
+                    if flags(old) != flags(new) {
+                        write!(f, " {} -> {}", flags(old), flags(new))?;
+                    }
+                    if old.indexed_content != new.indexed_content {
+                        write!(f, " CONTENT:[{}] -> [{}]", old.indexed_content.trim_end(), new.indexed_content.trim_end())?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::content_hash;
+
+    fn vox(id:u64, position:u32, content:&str) -> VoxEntry {
+        VoxEntry {
+            id, author: "alice".to_string(), log_id: "2021-07-24-log.txt".to_string(), position, date: "2021-07-24".to_string(),
+            content_hash: content_hash(content), content: content.to_string(), source: None,
+        }
+    }
+
+    // Indexes a vox as its content, flagging a song when it has ^s
+    fn index(vox:&VoxEntry) -> VoxIndexData {
+        VoxIndexData {
+            id: vox.id, indexed_content: format!("{} ", vox.content), has_song: vox.content.contains("^s"),
+            has_morshu: false, has_grant: false, vocab_version: "new".to_string(),
+        }
+    }
+
+    #[test]
+    fn sorts_voxes_into_what_would_change() {
+        let stored = vec![vox(10, 0, "hello"), vox(11, 1, "attention"), vox(12, 2, "alert"), vox(13, 3, "bye")];
+        let mut voxes = vec![vox(0, 0, "hello"), vox(0, 1, "attention"), vox(0, 2, "^s alert"), vox(0, 4, "hi")];
+
+        let diff = diff_voxes("2021-07-24-log.txt", &mut voxes, stored);
+        assert_eq!(diff.ingest(), IngestReport { new: 1, unchanged: 2, changed: 1, removed: 1 });
+        assert_eq!(voxes.iter().map(|vox| vox.id).collect::<Vec<_>>(), [10, 11, 12, 0]);
+        let text = diff.to_string();
+        assert!(text.contains("+ [4] alice: \"hi\""));
+        assert!(text.contains("~ [2] vox [12] alice: \"alert\" -> alice: \"^s alert\""));
+        assert!(text.contains("- [3] vox [13] alice: \"bye\""));
+    }
+
+    #[test]
+    fn only_rows_with_other_words_or_flags_change() {
+        let voxes = vec![vox(10, 0, "hello"), vox(11, 1, "attention"), vox(12, 2, "^s alert"), vox(0, 3, "hi")];
+        // 10 is indexed as it would be now, only with another vocab version, 11 by an older filter, 12 never was
+        let mut stored = vec![index(&voxes[0]), index(&voxes[1])];
+        stored[0].vocab_version = "old".to_string();
+        stored[1].indexed_content = "atention ".to_string();
+        let rows = voxes.iter().map(index).collect();
+
+        let diff = diff_index("2021-07-24-log.txt", &voxes, rows, stored);
+        let changes : Vec<(u32, Option<u64>)> = diff.changes.iter().map(|change| (change.position, change.old.as_ref().map(|old| old.id))).collect();
+        assert_eq!(changes, [(1, Some(11)), (2, None), (3, None)]);
+        assert!(diff.to_string().contains("# [1] vox [11] CONTENT:[atention] -> [attention]"));
+    }
+}
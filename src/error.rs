@@ -0,0 +1,97 @@
+use std::{fmt, io};
+use std::path::PathBuf;
+use tracing::error;
+
+pub type VoxResult<T> = std::result::Result<T, VoxError>;
+
+#[derive(Debug)]
+pub enum VoxError {
+    // Fetching a listing or log off the web failed
+    Network { url: String, source: reqwest::Error },
+    // A listing name, log body or setting couldn't be understood
+    Parse(String),
+    // Either backend, see `store`
+    Database(Box<dyn std::error::Error + Send + Sync>),
+    // The vocab couldn't be loaded, so nothing can be validated
+    Vocabulary(String),
+    Io { path: Option<PathBuf>, source: io::Error },
+    // Something required to run (credentials, paths) is missing
+    Config(String),
+    // A vox, log or other record asked for by id isn't stored
+    NotFound(String),
+    // Some listings of a run failed, the details were printed as they happened
+    ListingsFailed(usize),
+}
+
+impl VoxError {
+    pub fn network(url:&str, source:reqwest::Error) -> VoxError { VoxError::Network { url: url.to_string(), source } }
+    pub fn file(path:impl Into<PathBuf>, source:io::Error) -> VoxError { VoxError::Io { path: Some(path.into()), source } }
+}
+
+impl fmt::Display for VoxError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VoxError::Network { url, source } => write!(f, "network error fetching [{url}]: {source}"),
+            VoxError::Parse(reason) => write!(f, "parse error: {reason}"),
+            VoxError::Database(e) => write!(f, "database error: {e}"),
+            VoxError::Vocabulary(reason) => write!(f, "vocabulary error: {reason}"),
+            VoxError::Io { path: Some(path), source } => write!(f, "I/O error on [{}]: {source}", path.display()),
+            VoxError::Io { path: None, source } => write!(f, "I/O error: {source}"),
+            VoxError::Config(reason) => write!(f, "configuration error: {reason}"),
+            VoxError::NotFound(what) => write!(f, "not found: {what}"),
+            VoxError::ListingsFailed(count) => write!(f, "{count} listing(s) failed"),
+        }
+    }
+}
+
+impl std::error::Error for VoxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VoxError::Network { source, .. } => Some(source),
+            VoxError::Database(e) => Some(e.as_ref()),
+            VoxError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<mysql::Error> for VoxError {
+    fn from(e:mysql::Error) -> VoxError { VoxError::Database(Box::new(e)) }
+}
+
+impl From<mysql::UrlError> for VoxError {
+    fn from(e:mysql::UrlError) -> VoxError { VoxError::Database(Box::new(e)) }
+}
+
+impl From<rusqlite::Error> for VoxError {
+    fn from(e:rusqlite::Error) -> VoxError { VoxError::Database(Box::new(e)) }
+}
+
+impl From<io::Error> for VoxError {
+    fn from(source:io::Error) -> VoxError { VoxError::Io { path: None, source } }
+}
+
+// Listings that failed during a run, kept so the rest of the run can carry on
+#[derive(Default)]
+pub struct Failures {
+    failed: Vec<(String, VoxError)>,
+}
+
+impl Failures {
+    pub fn record(&mut self, listing_id:&str, e:VoxError) {
+        error!(listing = listing_id, error = %e, "Entry failed, skipping");
+        self.failed.push((listing_id.to_string(), e));
+    }
+
+    // Logs which listings failed and why, and turns them into the run's result
+    pub fn summarize(self) -> VoxResult<()> {
+        if self.failed.is_empty() {
+            return Ok(());
+        }
+        error!(count = self.failed.len(), "Listings failed");
+        for (listing_id, e) in &self.failed {
+            error!(listing = listing_id, error = %e, "Failed");
+        }
+        Err(VoxError::ListingsFailed(self.failed.len()))
+    }
+}
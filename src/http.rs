@@ -0,0 +1,192 @@
+// The HTTP client log sources fetch through, one per source and shared by its workers.
+//
+// Requests time out instead of hanging, and network errors, 5xx and 429 responses are retried with
+// exponential backoff (or after the Retry-After the server asked for). Requests to the same host are
+// spaced out to at most `requests_per_second`, across every worker.
+
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{self, HeaderMap};
+use reqwest::{StatusCode, Url};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::error::{VoxError, VoxResult};
+
+// No single wait, backoff or Retry-After, is longer than this
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpSettings {
+    pub connect_timeout: Duration,
+    // For the whole request, body included
+    pub timeout: Duration,
+    // Retries after the first attempt
+    pub retries: u32,
+    // Before the first retry, doubling for each one after
+    pub backoff: Duration,
+    // Per host, 0 for no limit
+    pub requests_per_second: f64,
+    pub user_agent: String,
+}
+
+impl Default for HttpSettings {
+    fn default() -> HttpSettings {
+        HttpSettings {
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(60),
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            requests_per_second: 2.0,
+            user_agent: concat!("voxcrawler/", env!("CARGO_PKG_VERSION")).to_string(),
+        }
+    }
+}
+
+// A response that made it, 2xx or 304, with its body already read
+pub struct HttpReply {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub text: String,
+}
+
+pub struct HttpClient {
+    client: Client,
+    settings: HttpSettings,
+    // When each host can next be sent a request
+    next_request: Mutex<HashMap<String, Instant>>,
+}
+
+impl HttpClient {
+    pub fn new(settings:&HttpSettings) -> VoxResult<HttpClient> {
+        let client = Client::builder()
+            .connect_timeout(settings.connect_timeout)
+            .timeout(settings.timeout)
+            .user_agent(&settings.user_agent)
+            .build()
+            .map_err(|e| VoxError::Config(format!("can't set up the HTTP client: {e}")))?;
+        Ok(HttpClient { client, settings: settings.clone(), next_request: Mutex::new(HashMap::new()) })
+    }
+
+    // Blocks until it's this host's turn
+    fn wait_turn(&self, url:&str) {
+        if self.settings.requests_per_second <= 0.0 {
+            return;
+        }
+        let host = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or_default();
+        let interval = Duration::from_secs_f64(1.0 / self.settings.requests_per_second);
+        let turn = {
+            let mut next_request = self.next_request.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let turn = next_request.get(&host).copied().filter(|&next| next > now).unwrap_or(now);
+            next_request.insert(host, turn + interval);
+            turn
+        };
+        thread::sleep(turn.saturating_duration_since(Instant::now()));
+    }
+
+    // GETs `url`, retrying what's worth retrying. Any other error status fails straight away.
+    pub fn get(&self, url:&str, headers:HeaderMap) -> VoxResult<HttpReply> {
+        let mut attempt = 0;
+        loop {
+            self.wait_turn(url);
+            let (error, retry_after) = match self.client.get(url).headers(headers.clone()).send() {
+                Ok(resp) if is_retryable(resp.status()) => {
+                    let retry_after = retry_after(&resp);
+                    (resp.error_for_status().expect_err("retryable statuses are errors"), retry_after)
+                },
+                Ok(resp) => {
+                    let resp = resp.error_for_status().map_err(|e| VoxError::network(url, e))?;
+                    let (status, headers) = (resp.status(), resp.headers().clone());
+                    match resp.text() {
+                        Ok(text) => return Ok(HttpReply { status, headers, text }),
+                        Err(e) => (e, None),
+                    }
+                },
+                // A bad URL won't get any better
+                Err(e) if e.is_builder() => return Err(VoxError::network(url, e)),
+                Err(e) => (e, None),
+            };
+            if attempt >= self.settings.retries {
+                return Err(VoxError::network(url, error));
+            }
+            let delay = retry_after.unwrap_or_else(|| self.settings.backoff.saturating_mul(1 << attempt.min(16))).min(MAX_BACKOFF);
+            attempt += 1;
+            warn!(url, %error, attempt, retries = self.settings.retries, delay_ms = delay.as_millis() as u64, "Fetching failed, retrying");
+            thread::sleep(delay);
+        }
+    }
+}
+
+fn is_retryable(status:StatusCode) -> bool { status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS }
+
+// Only the delay-in-seconds form, an HTTP date falls back on the usual backoff
+fn retry_after(resp:&Response) -> Option<Duration> {
+    resp.headers().get(header::RETRY_AFTER)?.to_str().ok()?.trim().parse().ok().map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tiny_http::{Header, Response as TestResponse, Server};
+
+    // Serves `statuses` in turn, then 200s, returning the base URL and how many requests came in
+    fn flaky_server(statuses:Vec<u16>) -> (String, Arc<AtomicUsize>) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let status = statuses.get(n).copied().unwrap_or(200);
+                let agent = request.headers().iter().find(|h| h.field.equiv("User-Agent")).map(|h| h.value.to_string()).unwrap_or_default();
+                let response = TestResponse::from_string(agent).with_status_code(status)
+                    .with_header(Header::from_bytes("Retry-After", "0").unwrap());
+                let _ = request.respond(response);
+            }
+        });
+        (url, hits)
+    }
+
+    fn quick() -> HttpSettings {
+        HttpSettings { backoff: Duration::from_millis(1), requests_per_second: 0.0, user_agent: "vox-test".to_string(), ..HttpSettings::default() }
+    }
+
+    #[test]
+    fn server_errors_are_retried() {
+        let (url, hits) = flaky_server(vec![503, 500, 429]);
+        let reply = HttpClient::new(&quick()).unwrap().get(&url, HeaderMap::new()).unwrap();
+        assert_eq!(reply.status, StatusCode::OK);
+        assert_eq!(reply.text, "vox-test");
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn retries_run_out_and_client_errors_fail_at_once() {
+        let (url, hits) = flaky_server(vec![502; 10]);
+        let settings = HttpSettings { retries: 2, ..quick() };
+        assert!(HttpClient::new(&settings).unwrap().get(&url, HeaderMap::new()).is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        let (url, hits) = flaky_server(vec![404]);
+        assert!(HttpClient::new(&quick()).unwrap().get(&url, HeaderMap::new()).is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn requests_to_a_host_are_spaced_out() {
+        let (url, _) = flaky_server(Vec::new());
+        let client = HttpClient::new(&HttpSettings { requests_per_second: 20.0, ..quick() }).unwrap();
+        let now = Instant::now();
+        for _ in 0..4 {
+            client.get(&url, HeaderMap::new()).unwrap();
+        }
+        // The first goes straight away, the other three wait 50ms each
+        assert!(now.elapsed() >= Duration::from_millis(150));
+    }
+}
@@ -0,0 +1,200 @@
+// Parser for the vox logs served off rook.zone.
+//
+// A log is a sequence of blocks:
+//
+//   log    := (blank | stray)* block*
+//   block  := header body
+//   header := "From " author ":" timestamp?
+//   body   := line* (up to the next header or the end of the log)
+//
+// The author is everything between "From " and the first ':', so names outside `\w` survive. Only a
+// timestamp can follow it, like `12:00`, `3:00pm` or `2021-07-24 20:01`, so a body line that happens
+// to start with "From the top: ..." stays in its vox.
+// Bodies keep every line, with blank lines at either end trimmed off. Anything that can't be
+// placed in a block is reported as a warning instead of being dropped silently.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt;
+
+lazy_static! { static ref TIMESTAMP_RX: Regex = Regex::new(r"(?i)^(\d{4}-\d{2}-\d{2}\s+)?\d{1,2}:\d{2}(:\d{2})?\s*([ap]m)?$").unwrap(); }
+
+pub struct ParsedVox {
+    pub author: String,
+    pub body: String,
+    // 1-based, inclusive, from the header to the last line of the body
+    pub first_line: usize,
+    pub last_line: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseWarning {
+    pub line: usize,
+    pub message: String,
+}
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result { write!(f, "line {}: {}", self.line, self.message) }
+}
+
+#[derive(Default)]
+pub struct ParsedLog {
+    pub voxes: Vec<ParsedVox>,
+    pub warnings: Vec<ParseWarning>,
+}
+
+enum Line<'a> {
+    Header { author: &'a str },
+    Blank,
+    Text(&'a str),
+}
+
+fn classify(line:&str) -> Line<'_> {
+    if line.trim().is_empty() {
+        return Line::Blank;
+    }
+    if let Some(rest) = line.strip_prefix("From ") {
+        if let Some((author, stamp)) = rest.split_once(':') {
+            let stamp = stamp.trim();
+            if stamp.is_empty() || TIMESTAMP_RX.is_match(stamp) {
+                return Line::Header { author: author.trim() };
+            }
+        }
+    }
+    Line::Text(line)
+}
+
+// A block being read, body lines are kept with their line numbers so blank ends can be trimmed
+struct OpenBlock<'a> {
+    author: &'a str,
+    header_line: usize,
+    lines: Vec<(usize, &'a str)>,
+}
+
+impl OpenBlock<'_> {
+    fn close(self, log:&mut ParsedLog) {
+        let body_lines : Vec<&(usize, &str)> = {
+            let start = self.lines.iter().position(|(_, line)| !line.trim().is_empty());
+            let end = self.lines.iter().rposition(|(_, line)| !line.trim().is_empty());
+            match (start, end) {
+                (Some(start), Some(end)) => self.lines[start..=end].iter().collect(),
+                _ => Vec::new(),
+            }
+        };
+        if self.author.is_empty() {
+            log.warnings.push(ParseWarning { line: self.header_line, message: "vox header has no author, skipping the vox".to_string() });
+            return;
+        }
+        let Some(&&(last_line, _)) = body_lines.last() else {
+            log.warnings.push(ParseWarning { line: self.header_line, message: format!("vox from [{}] has no body, skipping it", self.author) });
+            return;
+        };
+        log.voxes.push(ParsedVox {
+            author: self.author.to_string(),
+            body: body_lines.iter().map(|(_, line)| line.trim_end()).collect::<Vec<_>>().join("\n"),
+            first_line: self.header_line,
+            last_line,
+        });
+    }
+}
+
+pub fn parse(log:&str) -> ParsedLog {
+    let mut parsed = ParsedLog::default();
+    let mut open : Option<OpenBlock> = None;
+    for (index, raw_line) in log.lines().enumerate() {
+        let line_no = index + 1;
+        let line = raw_line.strip_suffix('\r').unwrap_or(raw_line);
+        match (classify(line), open.as_mut()) {
+            (Line::Header { author }, _) => {
+                if let Some(block) = open.take() {
+                    block.close(&mut parsed);
+                }
+                open = Some(OpenBlock { author, header_line: line_no, lines: Vec::new() });
+            },
+            (Line::Blank, Some(block)) => block.lines.push((line_no, line)),
+            (Line::Text(text), Some(block)) => {
+                if text.starts_with("From ") {
+                    parsed.warnings.push(ParseWarning { line: line_no, message: "line starts like a vox header but isn't one, keeping it as body text".to_string() });
+                }
+                block.lines.push((line_no, line));
+            },
+            (Line::Blank, None) => (),
+            (Line::Text(text), None) => parsed.warnings.push(ParseWarning { line: line_no, message: format!("text outside of any vox, ignoring it: [{}]", text.trim()) }),
+        }
+    }
+    if let Some(block) = open.take() {
+        block.close(&mut parsed);
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn fixture(name:&str) -> String { fs::read_to_string(format!("tests/fixtures/logs/{name}")).unwrap() }
+
+    #[test]
+    fn every_stored_log_parses_cleanly() {
+        for entry in fs::read_dir("voxes").unwrap() {
+            let path = entry.unwrap().path();
+            let parsed = parse(&fs::read_to_string(&path).unwrap());
+            assert!(parsed.warnings.is_empty(), "[{}] warned: {:?}", path.display(), parsed.warnings);
+            assert!(!parsed.voxes.is_empty(), "[{}] has no voxes", path.display());
+        }
+    }
+
+    #[test]
+    fn birthday_log_matches_the_original_regex() {
+        let parsed = parse(&fs::read_to_string("voxes/2021-07-24-birthdayLog.txt").unwrap());
+        let authors : Vec<&str> = parsed.voxes.iter().map(|vox| vox.author.as_str()).collect();
+        assert_eq!(authors, ["belbeeno", "Slio9", "Anthonyqvarnstrom", "jillofhearts", "mikamii", "pabs", "spoocecow", "Ellie",
+            "critttler", "justwhatever_idk", "mechone", "oakreef", "R_CADEZONE", "wildgabu", "impyFrost", "Frums"]);
+        assert_eq!(parsed.voxes[1].body, "happy birthday chess. I got you t.");
+        assert_eq!((parsed.voxes[1].first_line, parsed.voxes[1].last_line), (4, 5));
+    }
+
+    #[test]
+    fn authors_outside_word_characters_are_kept() {
+        let parsed = parse(&fixture("odd_authors.txt"));
+        let authors : Vec<&str> = parsed.voxes.iter().map(|vox| vox.author.as_str()).collect();
+        assert_eq!(authors, ["Mr. Vox", "dr-vox", "vöx", "[bot]vox"]);
+        assert!(parsed.warnings.is_empty());
+    }
+
+    #[test]
+    fn multi_line_bodies_are_kept_whole() {
+        let parsed = parse(&fixture("multi_line.txt"));
+        assert_eq!(parsed.voxes.len(), 2);
+        assert_eq!(parsed.voxes[0].body, "^song ^bpm=120 n1 *+2\n*+4 *+5\n\n*+7 ^song");
+        assert_eq!((parsed.voxes[0].first_line, parsed.voxes[0].last_line), (1, 5));
+        assert_eq!(parsed.voxes[1].body, "hello world");
+    }
+
+    #[test]
+    fn body_lines_starting_with_from_stay_in_their_vox() {
+        let parsed = parse(&fixture("body_from.txt"));
+        let authors : Vec<&str> = parsed.voxes.iter().map(|vox| vox.author.as_str()).collect();
+        assert_eq!(authors, ["alice", "bob"]);
+        assert_eq!(parsed.voxes[0].body, "^song ^bpm=120 n1\nFrom the top: ^a ^b\nn2 ^song");
+        let lines : Vec<usize> = parsed.warnings.iter().map(|warning| warning.line).collect();
+        assert_eq!(lines, [3]);
+    }
+
+    #[test]
+    fn crlf_line_endings_are_stripped() {
+        let parsed = parse("From alice: 12:00\r\nhello world\r\n\r\nFrom bob:\r\nattention\r\n");
+        assert_eq!(parsed.voxes.len(), 2);
+        assert_eq!(parsed.voxes[0].body, "hello world");
+        assert_eq!(parsed.voxes[1].body, "attention");
+    }
+
+    #[test]
+    fn malformed_blocks_are_reported() {
+        let parsed = parse(&fixture("malformed.txt"));
+        let bodies : Vec<&str> = parsed.voxes.iter().map(|vox| vox.body.as_str()).collect();
+        assert_eq!(bodies, ["hello", "From nobody said this\nstill hello"]);
+        let lines : Vec<usize> = parsed.warnings.iter().map(|warning| warning.line).collect();
+        assert_eq!(lines, [1, 6, 9, 12]);
+    }
+}
@@ -0,0 +1,38 @@
+// Where progress, timings and warnings go: leveled events on stderr, as text or one JSON object per
+// line, so stdout is left to what the commands themselves print.
+//
+// Pipeline events are grouped in spans, `job` > `listing` > `fetch`, `commit` and `index`, each with
+// what it's working on. `--log-level` takes a level, or directives like `info,filters=trace` to see
+// what every filter made of every vox without turning everything else up.
+
+use serde::Deserialize;
+use std::io::{self, IsTerminal};
+use tracing_subscriber::EnvFilter;
+
+use crate::error::{VoxError, VoxResult};
+
+pub const DEFAULT_LEVEL: &str = "info";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+// `level` is a level (error, warn, info, debug, trace) or comma separated `target=level` directives
+pub fn filter(level:&str) -> VoxResult<EnvFilter> {
+    EnvFilter::builder().parse(level)
+        .map_err(|e| VoxError::Config(format!("[{level}] isn't a log level or filter: {e}")))
+}
+
+// Once per process, before anything is logged
+pub fn init(level:&str, format:LogFormat) -> VoxResult<()> {
+    let builder = tracing_subscriber::fmt().with_env_filter(filter(level)?).with_writer(io::stderr);
+    let installed = match format {
+        LogFormat::Text => builder.with_ansi(io::stderr().is_terminal()).try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).try_init(),
+    };
+    installed.map_err(|e| VoxError::Config(format!("can't set up logging: {e}")))
+}
@@ -0,0 +1,337 @@
+// Renders the `^song` sections of a vox to a standard MIDI file (format 0, one track).
+//
+// Inside a song every word, `*` and bare pitch is one note as long as the current `^l=` (4 for a
+// quarter, dotted lengths add half again per dot), at the current `^bpm=`. Pitches are semitones
+// from middle C. The note samples (`n1`, `kk14`, `d2`, ... and their long forms) get the closest
+// General MIDI program or drum, any other word is sung by a choir. Runs of `.` and `rn` are rests.
+
+use std::collections::HashMap;
+
+use crate::error::{VoxError, VoxResult};
+use crate::vox_lang::{Control, Node, Pitch, Vox};
+use crate::vox_utils::filters;
+
+const TICKS_PER_QUARTER: u32 = 480;
+const DEFAULT_BPM: u32 = 120;
+// Tempo events hold microseconds per quarter in 24 bits, so anything under 4 bpm plays at about 3.6
+const MAX_TEMPO: u32 = 0xff_ffff;
+// Delta times are at most 4 bytes of 7 bits, longer rests are cut to about 39 hours at 120 bpm
+const MAX_DELTA: u32 = 0x0fff_ffff;
+const DEFAULT_LENGTH: &str = "4";
+const MIDDLE_C: i32 = 60;
+const VELOCITY: u8 = 100;
+const DRUM_CHANNEL: u8 = 9;
+// Choir Aahs, for words that aren't note samples
+const VOICE_PROGRAM: u8 = 52;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Instrument {
+    // 0-based General MIDI program, pitched
+    Program(u8),
+    // Key on the drum channel, pitch is ignored
+    Drum(u8),
+}
+
+// `sample` is the long form, see `SHORTHAND_DICTIONARY`
+fn instrument(sample:&str) -> Option<Instrument> {
+    use Instrument::*;
+    let instrument = match sample {
+        "cnote" => Program(0),
+        "catnote" => Program(81),
+        "cuicanote" => Drum(79),
+        "dootnote" => Program(56),
+        "yossynote" => Program(53),
+        "puhnote" => Program(58),
+        "bupnote" => Program(62),
+        "dantnote" => Program(52),
+        "downote" => Program(38),
+        "slapnote" => Program(36),
+        "jarnote" => Program(76),
+        "orchnote" => Program(55),
+        "shynote" => Program(85),
+        "morshunote" => Program(54),
+        "hazymazenote" => Program(11),
+        "hauntnote" => Program(91),
+        "pizzicatonote" => Program(45),
+        "zunnote" => Program(33),
+        "banjonote" | "banjonote2" | "banjonote3" => Program(105),
+        "diddynote" | "diddynote2" | "diddynote3" => Program(80),
+        "kk_na" | "kk_mi" | "kk_me" | "kk_o" | "kk_oh" | "kk_way" | "kk_now" | "kk_howl" | "kk_hm" | "kk_hmlow" => Program(53),
+        "kk_whistle" => Program(78),
+        "kk_snare" => Drum(38),
+        "kk_snare2" => Drum(40),
+        "kk_hat" => Drum(42),
+        "sonic_snare" => Drum(38),
+        "sonic_kick" => Drum(36),
+        "sonic_go" => Program(54),
+        "hazymazedrum" => Drum(47),
+        "hazymazewood" => Drum(76),
+        "yosbongonote" => Drum(60),
+        _ => return None,
+    };
+    Some(instrument)
+}
+
+fn is_rest(sample:&str) -> bool { sample == "restnote" }
+
+// Note length in ticks for a `^l=` argument like `8` or `4..`
+fn length_ticks(arg:&str) -> u32 {
+    let dots = arg.len() - arg.trim_end_matches('.').len();
+    let whole = TICKS_PER_QUARTER * 4;
+    let mut part = whole / arg.trim_end_matches('.').parse::<u32>().unwrap_or(4).max(1);
+    let mut ticks = part;
+    for _ in 0..dots {
+        part /= 2;
+        ticks += part;
+    }
+    ticks
+}
+
+fn write_vlq(bytes:&mut Vec<u8>, value:u32) {
+    let mut value = value.min(MAX_DELTA);
+    let mut buffer = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        buffer.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.extend(buffer.iter().rev());
+}
+
+// Events of the single track, `pending` is the time since the last event
+#[derive(Default)]
+struct Track {
+    bytes: Vec<u8>,
+    pending: u32,
+}
+
+impl Track {
+    fn event(&mut self, data:&[u8]) {
+        write_vlq(&mut self.bytes, self.pending);
+        self.pending = 0;
+        self.bytes.extend_from_slice(data);
+    }
+
+    fn tempo(&mut self, bpm:u32) {
+        let micros = (60_000_000 / bpm.max(1)).min(MAX_TEMPO);
+        self.event(&[0xff, 0x51, 0x03, (micros >> 16) as u8, (micros >> 8) as u8, micros as u8]);
+    }
+
+    fn note(&mut self, channel:u8, key:u8, ticks:u32) {
+        self.event(&[0x90 | channel, key, VELOCITY]);
+        self.pending = ticks;
+        self.event(&[0x80 | channel, key, 0]);
+    }
+
+    fn rest(&mut self, ticks:u32) { self.pending = self.pending.saturating_add(ticks); }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.event(&[0xff, 0x2f, 0x00]);
+        self.bytes
+    }
+}
+
+struct Renderer {
+    track: Track,
+    note_ticks: u32,
+    // The sample `*` and bare pitches replay
+    previous: Option<String>,
+    channels: HashMap<u8, u8>,
+    programs: [Option<u8>; 16],
+}
+
+impl Renderer {
+    fn new() -> Renderer {
+        let mut track = Track::default();
+        track.tempo(DEFAULT_BPM);
+        Renderer { track, note_ticks: length_ticks(DEFAULT_LENGTH), previous: None, channels: HashMap::new(), programs: [None; 16] }
+    }
+
+    // Every program gets its own channel, once they run out the last one is reprogrammed as needed
+    fn channel_for(&mut self, program:u8) -> u8 {
+        let next = self.channels.len() as u8;
+        let channel = match self.channels.get(&program) {
+            Some(&channel) => channel,
+            None if next < 15 => {
+                let channel = if next >= DRUM_CHANNEL { next + 1 } else { next };
+                self.channels.insert(program, channel);
+                channel
+            },
+            None => 15,
+        };
+        if self.programs[channel as usize] != Some(program) {
+            self.track.event(&[0xc0 | channel, program]);
+            self.programs[channel as usize] = Some(program);
+        }
+        channel
+    }
+
+    fn control(&mut self, control:&Control) {
+        match (control.name.as_str(), &control.arg) {
+            ("bpm", Some(arg)) => self.track.tempo(arg.parse().unwrap_or(DEFAULT_BPM)),
+            ("l", Some(arg)) => self.note_ticks = length_ticks(arg),
+            _ => (),
+        }
+    }
+
+    fn play(&mut self, sample:&str, pitch:&Pitch) {
+        if is_rest(sample) {
+            self.track.rest(self.note_ticks);
+            return;
+        }
+        match instrument(sample).unwrap_or(Instrument::Program(VOICE_PROGRAM)) {
+            Instrument::Drum(key) => self.track.note(DRUM_CHANNEL, key, self.note_ticks),
+            Instrument::Program(program) => {
+                let channel = self.channel_for(program);
+                let key = (MIDDLE_C + pitch.semitones()).clamp(0, 127) as u8;
+                self.track.note(channel, key, self.note_ticks);
+            },
+        }
+    }
+
+    fn replay(&mut self, pitch:&Pitch) {
+        if let Some(sample) = self.previous.clone() {
+            self.play(&sample, pitch);
+        }
+    }
+
+    fn node(&mut self, node:&Node) {
+        match node {
+            Node::Word { name, pitch } => {
+                let sample = filters::remap_note_shorthand(name.to_lowercase());
+                self.play(&sample, pitch);
+                self.previous = Some(sample);
+            },
+            Node::Repeat { pitch } => self.replay(pitch),
+            Node::Pitch(modifier) => self.replay(&Pitch { prefix: Some(modifier.clone()), suffix: None }),
+            Node::Control(control) => self.control(control),
+            Node::Rest { count } => self.track.rest(self.note_ticks.saturating_mul(u32::try_from(*count).unwrap_or(u32::MAX))),
+            _ => (),
+        }
+    }
+}
+
+// The whole file, or an error if the vox has no `^song` to render
+pub fn render(vox:&Vox) -> VoxResult<Vec<u8>> {
+    let mut renderer = Renderer::new();
+    let mut songs = 0;
+    for node in &vox.nodes {
+        if let Node::Song(song) = node {
+            songs += 1;
+            renderer.control(&song.open);
+            for node in &song.nodes {
+                renderer.node(node);
+            }
+        }
+    }
+    if songs == 0 {
+        return Err(VoxError::Parse("vox has no ^song section to render".to_string()));
+    }
+
+    let track = renderer.track.finish();
+    let mut bytes = Vec::with_capacity(track.len() + 22);
+    bytes.extend_from_slice(b"MThd");
+    bytes.extend_from_slice(&6u32.to_be_bytes());
+    bytes.extend_from_slice(&0u16.to_be_bytes());
+    bytes.extend_from_slice(&1u16.to_be_bytes());
+    bytes.extend_from_slice(&(TICKS_PER_QUARTER as u16).to_be_bytes());
+    bytes.extend_from_slice(b"MTrk");
+    bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&track);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vox_lang;
+
+    fn render_str(src:&str) -> Vec<u8> { render(&vox_lang::parse(src).unwrap()).unwrap() }
+
+    // The track's events, after the header and the default tempo
+    fn events(bytes:&[u8]) -> &[u8] { &bytes[22 + 7..] }
+
+    #[test]
+    fn variable_length_quantities_encode() {
+        for (value, expected) in [(0, vec![0x00]), (0x7f, vec![0x7f]), (0x80, vec![0x81, 0x00]), (1920, vec![0x8f, 0x00]), (0x0fff_ffff, vec![0xff, 0xff, 0xff, 0x7f]), (u32::MAX, vec![0xff, 0xff, 0xff, 0x7f])] {
+            let mut bytes = Vec::new();
+            write_vlq(&mut bytes, value);
+            assert_eq!(bytes, expected, "{value:#x}");
+        }
+    }
+
+    #[test]
+    fn lengths_and_dots() {
+        assert_eq!(length_ticks("4"), 480);
+        assert_eq!(length_ticks("16"), 120);
+        assert_eq!(length_ticks("8."), 360);
+        assert_eq!(length_ticks("4.."), 840);
+        assert_eq!(length_ticks("1"), 1920);
+    }
+
+    #[test]
+    fn header_and_default_tempo() {
+        let bytes = render_str("^song");
+        assert_eq!(&bytes[..14], b"MThd\0\0\0\x06\0\0\0\x01\x01\xe0");
+        assert_eq!(&bytes[14..18], b"MTrk");
+        // 500000us per quarter is 120 bpm
+        assert_eq!(&bytes[22..29], &[0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20]);
+        assert_eq!(events(&bytes), &[0x00, 0xff, 0x2f, 0x00]);
+    }
+
+    #[test]
+    fn tempos_too_slow_for_midi_are_clamped() {
+        assert_eq!(&events(&render_str("^song ^bpm=2"))[..7], &[0x00, 0xff, 0x51, 0x03, 0xff, 0xff, 0xff]);
+        assert_eq!(&events(&render_str("^song ^bpm=4"))[..7], &[0x00, 0xff, 0x51, 0x03, 0xe4, 0xe1, 0xc0]);
+    }
+
+    #[test]
+    fn rests_too_long_for_midi_are_clamped() {
+        // 3 million whole notes of rest overflow a u32 of ticks
+        let bytes = render_str(&format!("^song ^l=1 {} n1", ".".repeat(3_000_000)));
+        assert_eq!(&events(&bytes)[..8], &[0xff, 0xff, 0xff, 0x7f, 0xc0, 0, 0x00, 0x90]);
+    }
+
+    #[test]
+    fn pitches_repeats_and_rests() {
+        let bytes = render_str("^song ^l=8 n1+2 *-3 .. -1");
+        assert_eq!(events(&bytes), &[
+            0x00, 0xc0, 0, // cnote gets channel 0
+            0x00, 0x90, 62, VELOCITY, 0x81, 0x70, 0x80, 62, 0,
+            0x00, 0x90, 57, VELOCITY, 0x81, 0x70, 0x80, 57, 0,
+            // two eighths of rest before the bare pitch replays cnote
+            0x83, 0x60, 0x90, 59, VELOCITY, 0x81, 0x70, 0x80, 59, 0,
+            0x00, 0xff, 0x2f, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn drums_skip_programs_and_text_outside_songs_is_silent() {
+        let bytes = render_str("hello ^song ^bpm=60 d2 kk14 ^song world");
+        assert_eq!(events(&bytes), &[
+            0x00, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40,
+            0x00, 0x99, 36, VELOCITY, 0x83, 0x60, 0x89, 36, 0,
+            0x00, 0x99, 42, VELOCITY, 0x83, 0x60, 0x89, 42, 0,
+            0x00, 0xff, 0x2f, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn every_song_in_the_birthday_log_renders() {
+        let log = std::fs::read_to_string("voxes/2021-07-24-birthdayLog.txt").unwrap();
+        let mut rendered = 0;
+        for vox in crate::log_parser::parse(&log).voxes {
+            let parsed = vox_lang::parse(&vox.body).unwrap();
+            if parsed.nodes.iter().any(|node| matches!(node, Node::Song(_))) {
+                assert!(render(&parsed).unwrap().len() > 26);
+                rendered += 1;
+            }
+        }
+        assert!(rendered > 5);
+    }
+
+    #[test]
+    fn voxes_without_songs_are_refused() {
+        assert!(render(&vox_lang::parse("hello world").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,316 @@
+// What indexing did with the words it couldn't take as they were, written to the report directory
+// as one file a day, `VoxReport_<date>.<txt|jsonl|csv>`, that every run appends to.
+//
+// The text format is the one people read. JSON Lines and CSV write the same records for scripts:
+// one `listing` record per indexed log, with its timing, then one record per word that was
+//   dropped   not in the vocab, at the `vocab` index step, with `detail` the suggested words
+//   split     a compound indexed as the vocab words it's made of, at the `decompose` index step, with `detail` the parts
+// The filters run before those steps only rewrite the vox's text, they never drop a word on their
+// own. `--log-level filters=trace` shows what each of them did.
+// Every record has the `run` it came from, when that run started.
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::error::{VoxError, VoxResult};
+use crate::store::VoxEntry;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Jsonl,
+    Csv,
+}
+
+impl ReportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Text => "txt",
+            ReportFormat::Jsonl => "jsonl",
+            ReportFormat::Csv => "csv",
+        }
+    }
+}
+
+// A word `index_log` dropped because it isn't in the vocab
+pub struct Rejected {
+    pub vox_id: u64,
+    pub word: String,
+    // Closest vocab words, best first
+    pub suggestions: Vec<String>,
+}
+impl fmt::Display for Rejected {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] has word [{}] that is not in the vocab", self.vox_id, self.word)?;
+        if !self.suggestions.is_empty() {
+            write!(f, " (did you mean [{}]?)", self.suggestions.join("], ["))?;
+        }
+        Ok(())
+    }
+}
+
+// A word `index_log` indexed as the vocab words it's made of
+pub struct Decomposed {
+    pub vox_id: u64,
+    pub word: String,
+    pub parts: Vec<String>,
+}
+impl fmt::Display for Decomposed {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result { write!(f, "[{}] has word [{}], indexed as [{}]", self.vox_id, self.word, self.parts.join("] + [")) }
+}
+
+// What indexing a log had to do with words that aren't in the vocab
+#[derive(Default)]
+pub struct IndexReport {
+    pub voxes: usize,
+    pub rejected: Vec<Rejected>,
+    pub decomposed: Vec<Decomposed>,
+}
+
+// A word the vocab doesn't have, across every vox it was dropped from
+pub struct OovWord {
+    pub word: String,
+    pub voxes: usize,
+    pub authors: BTreeSet<String>,
+    // Log dates, YYYY-MM-DD
+    pub first_seen: String,
+    pub last_seen: String,
+    pub suggestions: Vec<String>,
+}
+impl fmt::Display for OovWord {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] in [{}] vox(es) by [{}] author(s), [{}] to [{}]", self.word, self.voxes, self.authors.len(), self.first_seen, self.last_seen)?;
+        if !self.suggestions.is_empty() {
+            write!(f, " (did you mean: {})", self.suggestions.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// Adds up the words indexing dropped, for `oov-report`
+#[derive(Default)]
+pub struct OovTally {
+    words: HashMap<String, OovWord>,
+}
+
+impl OovTally {
+    // `rejected` is what indexing `vox` dropped
+    pub fn add(&mut self, vox:&VoxEntry, rejected:&[Rejected]) {
+        for rejected in rejected {
+            let word = self.words.entry(rejected.word.clone()).or_insert_with(|| OovWord {
+                word: rejected.word.clone(),
+                voxes: 0,
+                authors: BTreeSet::new(),
+                first_seen: vox.date.clone(),
+                last_seen: vox.date.clone(),
+                suggestions: rejected.suggestions.clone(),
+            });
+            word.voxes += 1;
+            word.authors.insert(vox.author.clone());
+            word.first_seen = word.first_seen.clone().min(vox.date.clone());
+            word.last_seen = word.last_seen.clone().max(vox.date.clone());
+        }
+    }
+
+    // Best candidates for the vocab first: the most used, then the most widely used
+    pub fn ranked(self) -> Vec<OovWord> {
+        let mut words : Vec<OovWord> = self.words.into_values().collect();
+        words.sort_by(|a, b| b.voxes.cmp(&a.voxes).then(b.authors.len().cmp(&a.authors.len())).then_with(|| a.word.cmp(&b.word)));
+        words
+    }
+}
+
+// One row of the JSON Lines and CSV reports, see the top of this file
+#[derive(Serialize, Debug, PartialEq)]
+struct Record<'a> {
+    run: &'a str,
+    kind: &'static str,
+    listing: &'a str,
+    vox_id: Option<u64>,
+    token: Option<&'a str>,
+    reason: Option<&'static str>,
+    // The indexing step that dropped or split the word, `vocab` or `decompose`
+    index_step: Option<&'static str>,
+    // Space separated, the suggestions for a dropped word or the parts of a split one
+    detail: Option<String>,
+    // Only on `listing` records
+    voxes: Option<usize>,
+    index_ms: Option<u128>,
+}
+
+fn records<'a>(run:&'a str, log_id:&'a str, index_ms:u128, report:&'a IndexReport) -> Vec<Record<'a>> {
+    let mut records = vec![Record {
+        run, kind: "listing", listing: log_id,
+        vox_id: None, token: None, reason: None, index_step: None, detail: None,
+        voxes: Some(report.voxes),
+        index_ms: Some(index_ms),
+    }];
+    records.extend(report.decomposed.iter().map(|decomposed| Record {
+        run, kind: "split", listing: log_id,
+        vox_id: Some(decomposed.vox_id),
+        token: Some(&decomposed.word),
+        reason: Some("compound"),
+        index_step: Some("decompose"),
+        detail: Some(decomposed.parts.join(" ")),
+        voxes: None, index_ms: None,
+    }));
+    records.extend(report.rejected.iter().map(|rejected| Record {
+        run, kind: "dropped", listing: log_id,
+        vox_id: Some(rejected.vox_id),
+        token: Some(&rejected.word),
+        reason: Some("not_in_vocab"),
+        index_step: Some("vocab"),
+        detail: Some(rejected.suggestions.join(" ")),
+        voxes: None, index_ms: None,
+    }));
+    records
+}
+
+fn write_text(file:&mut File, log_id:&str, report:&IndexReport) -> std::io::Result<()> {
+    writeln!(file, "=== Report for [{}] - Error Count: {} ===", log_id, report.rejected.len())?;
+    for decomposed in &report.decomposed {
+        writeln!(file, "[{}] - {} indexed as {}", decomposed.vox_id, decomposed.word, decomposed.parts.join(" + "))?;
+    }
+    if report.rejected.is_empty() {
+        return writeln!(file, "No errors detected!  Great job everyone!");
+    }
+    for rejected in &report.rejected {
+        write!(file, "[{}] - {}", rejected.vox_id, rejected.word)?;
+        if !rejected.suggestions.is_empty() {
+            write!(file, " (did you mean: {})", rejected.suggestions.join(", "))?;
+        }
+        writeln!(file)?;
+    }
+    Ok(())
+}
+
+// Writes the reports of one run, shared by its workers
+pub struct Reporter {
+    dir: PathBuf,
+    format: ReportFormat,
+    // When the run started, on every record
+    run: String,
+    // Workers share the day's file, so they take turns writing to it
+    lock: Mutex<()>,
+}
+
+impl Reporter {
+    pub fn new(dir:&Path, format:ReportFormat) -> Reporter {
+        Reporter {
+            dir: dir.to_path_buf(),
+            format,
+            run: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(format!("VoxReport_{}.{}", Utc::now().format("%F"), self.format.extension()))
+    }
+
+    // Creates the report directory if it isn't there yet, returns the file written to
+    pub fn write(&self, log_id:&str, index_ms:u128, report:&IndexReport) -> VoxResult<PathBuf> {
+        let path = self.path();
+        let on_err = |e| VoxError::file(&path, e);
+
+        let _turn = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        fs::create_dir_all(&self.dir).map_err(|e| VoxError::file(&self.dir, e))?;
+        let mut file = File::options().append(true).create(true).open(&path).map_err(on_err)?;
+        match self.format {
+            ReportFormat::Text => write_text(&mut file, log_id, report).map_err(on_err)?,
+            ReportFormat::Jsonl => {
+                for record in records(&self.run, log_id, index_ms, report) {
+                    let line = serde_json::to_string(&record).map_err(|e| VoxError::Parse(format!("can't write a report record: {e}")))?;
+                    writeln!(file, "{line}").map_err(on_err)?;
+                }
+            },
+            ReportFormat::Csv => {
+                // A new day's file gets the header
+                let is_new = file.metadata().map_err(on_err)?.len() == 0;
+                let mut csv = csv::WriterBuilder::new().has_headers(is_new).from_writer(file);
+                for record in records(&self.run, log_id, index_ms, report) {
+                    csv.serialize(record).map_err(|e| VoxError::Parse(format!("can't write to [{}]: {e}", path.display())))?;
+                }
+                csv.flush().map_err(on_err)?;
+            },
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> IndexReport {
+        IndexReport {
+            voxes: 2,
+            rejected: vec![Rejected { vox_id: 7, word: "helo".to_string(), suggestions: vec!["hello".to_string(), "help".to_string()] }],
+            decomposed: vec![Decomposed { vox_id: 8, word: "funnybro".to_string(), parts: vec!["funny".to_string(), "bro".to_string()] }],
+        }
+    }
+
+    #[test]
+    fn oov_words_rank_by_use_then_authors() {
+        let vox = |author:&str, date:&str| VoxEntry {
+            id: 1, author: author.to_string(), log_id: format!("{date}-log.txt"), position: 0, date: date.to_string(),
+            content: String::new(), content_hash: String::new(), source: None,
+        };
+        let dropped = |words:&[&str]| words.iter().map(|word| Rejected { vox_id: 1, word: word.to_string(), suggestions: Vec::new() }).collect::<Vec<_>>();
+        let mut tally = OovTally::default();
+        tally.add(&vox("alice", "2021-07-24"), &dropped(&["helo", "zzt"]));
+        tally.add(&vox("alice", "2021-07-02"), &dropped(&["zzt"]));
+        tally.add(&vox("bob", "2021-08-01"), &dropped(&["helo", "brr"]));
+        tally.add(&vox("bob", "2021-08-02"), &dropped(&["brr"]));
+
+        let ranked = tally.ranked();
+        let words : Vec<&str> = ranked.iter().map(|word| word.word.as_str()).collect();
+        assert_eq!(words, ["helo", "brr", "zzt"]);
+        assert_eq!(ranked[0].authors.len(), 2);
+        assert_eq!((ranked[0].first_seen.as_str(), ranked[0].last_seen.as_str()), ("2021-07-24", "2021-08-01"));
+        assert_eq!((ranked[2].first_seen.as_str(), ranked[2].last_seen.as_str()), ("2021-07-02", "2021-07-24"));
+    }
+
+    #[test]
+    fn every_format_creates_the_directory_and_appends() {
+        let report = sample();
+        for format in [ReportFormat::Text, ReportFormat::Jsonl, ReportFormat::Csv] {
+            let scratch = tempfile::tempdir().unwrap();
+            let dir = scratch.path().join("nested");
+            let reporter = Reporter::new(&dir, format);
+            reporter.write("2021-07-24-a.txt", 12, &report).unwrap();
+            reporter.write("2021-07-25-b.txt", 3, &IndexReport::default()).unwrap();
+            let text = fs::read_to_string(reporter.path()).unwrap();
+            match format {
+                ReportFormat::Text => {
+                    assert!(text.contains("=== Report for [2021-07-24-a.txt] - Error Count: 1 ==="));
+                    assert!(text.contains("[7] - helo (did you mean: hello, help)"));
+                    assert!(text.contains("[8] - funnybro indexed as funny + bro"));
+                },
+                ReportFormat::Jsonl => {
+                    let lines : Vec<serde_json::Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+                    assert_eq!(lines.len(), 4);
+                    assert_eq!(lines[0]["index_ms"], 12);
+                    assert_eq!(lines[2]["token"], "helo");
+                    assert_eq!(lines[2]["index_step"], "vocab");
+                    assert_eq!(lines[3]["kind"], "listing");
+                },
+                ReportFormat::Csv => {
+                    let lines : Vec<&str> = text.lines().collect();
+                    // One header for both writes
+                    assert_eq!(lines.len(), 5);
+                    assert_eq!(lines[0], "run,kind,listing,vox_id,token,reason,index_step,detail,voxes,index_ms");
+                    assert!(lines[3].ends_with(",dropped,2021-07-24-a.txt,7,helo,not_in_vocab,vocab,hello help,,"));
+                },
+            }
+        }
+    }
+}
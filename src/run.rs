@@ -0,0 +1,87 @@
+// What a command writes to while it works through logs, passed down the pipeline instead of kept in
+// globals. It owns the dry-run log and the reports, when the run started, and whether it's a dry
+// run, so a dry run takes the same path as a real one and only stops short of writing. Progress goes
+// to the log, see `logging`.
+//
+// A dry run writes nothing but its log: `commit` stages the voxes it would have written here for
+// `index_log` to pick up, and the reports are left alone.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::error::{VoxError, VoxResult};
+use crate::report::{IndexReport, Reporter};
+use crate::store::VoxEntry;
+
+pub struct RunContext {
+    started: Instant,
+    // Dry runs only, everything said is written here too, stamped with the ms since the run started
+    dry_run_log: Option<(PathBuf, Mutex<File>)>,
+    // Real runs only
+    reporter: Option<Reporter>,
+    // What a dry run would have committed, by log id
+    staged: Mutex<HashMap<String, Vec<VoxEntry>>>,
+}
+
+impl RunContext {
+    fn with(reporter:Option<Reporter>, dry_run_log:Option<(PathBuf, Mutex<File>)>) -> RunContext {
+        RunContext {
+            started: Instant::now(),
+            dry_run_log,
+            reporter,
+            staged: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn new(reporter:Reporter) -> RunContext { RunContext::with(Some(reporter), None) }
+
+    // Creates the dry-run log at `path`, or empties it
+    pub fn dry_run(path:&Path) -> VoxResult<RunContext> {
+        let file = File::create(path).map_err(|e| VoxError::file(path, e))?;
+        Ok(RunContext::with(None, Some((path.to_path_buf(), Mutex::new(file)))))
+    }
+
+    pub fn is_dry_run(&self) -> bool { self.dry_run_log.is_some() }
+
+    pub fn elapsed(&self) -> Duration { self.started.elapsed() }
+
+    // Logged at info, and written to the dry-run log
+    pub fn say(&self, line:impl Display) {
+        let line = line.to_string();
+        info!("{line}");
+        self.note(line);
+    }
+
+    // Only to the dry-run log, for what's too long to log
+    pub fn note(&self, line:impl Display) {
+        let Some((path, file)) = &self.dry_run_log else { return };
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(file, "[{}] {line}", self.elapsed().as_millis()) {
+            warn!(path = %path.display(), error = %e, "Couldn't write to the dry-run log");
+        }
+    }
+
+    // Writes a log's report, unless it's a dry run
+    pub fn report(&self, log_id:&str, index_ms:u128, report:&IndexReport) -> VoxResult<()> {
+        if let Some(reporter) = &self.reporter {
+            let path = reporter.write(log_id, index_ms, report)?;
+            info!(path = %path.display(), "Report written");
+        }
+        Ok(())
+    }
+
+    pub fn stage(&self, log_id:&str, voxes:Vec<VoxEntry>) {
+        self.staged.lock().unwrap_or_else(|e| e.into_inner()).insert(log_id.to_string(), voxes);
+    }
+
+    // The voxes a dry run staged for `log_id`, if it got that far
+    pub fn take_staged(&self, log_id:&str) -> Option<Vec<VoxEntry>> {
+        self.staged.lock().unwrap_or_else(|e| e.into_inner()).remove(log_id)
+    }
+}
@@ -0,0 +1,288 @@
+// Read-only JSON API over the same `voxes` and `vox_meta` tables the crawler fills.
+//
+//   GET /health              schema version, 503 if the DB can't be reached
+//   GET /search?q=...        ranked matches, with `song`, `morshu`, `grant`, `author`, `since`, `until`
+//   GET /vox/{id}            one vox
+//   GET /log/{log_id}        every vox of a log, in order
+//   GET /authors             authors and how many voxes they have, most first
+//
+// Lists take `page` (from 1) and `per_page` (up to MAX_PER_PAGE), the store only reads that page.
+// Requests are handled one at a time, each on a connection taken from the store's pool, so one the
+// database dropped while the API sat idle is never reused.
+
+use chrono::NaiveDate;
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tiny_http::{Header, Method, Response, Server};
+use tracing::{error, info, warn};
+
+use crate::error::{VoxError, VoxResult};
+use crate::store::{SearchQuery, StorePool, VoxStore};
+use crate::vox_utils::filters;
+
+const DEFAULT_PER_PAGE: usize = 20;
+const MAX_PER_PAGE: usize = 100;
+
+// What a request gets back, before it's written out
+#[derive(Debug, PartialEq)]
+pub struct Reply {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Reply {
+    fn ok(body:Value) -> Reply { Reply { status: 200, body } }
+    fn error(status:u16, message:impl Into<String>) -> Reply { Reply { status, body: json!({ "error": message.into() }) } }
+}
+
+#[derive(Serialize)]
+struct Page<T:Serialize> {
+    page: usize,
+    per_page: usize,
+    // Whether asking for the next page would return anything
+    has_more: bool,
+    results: Vec<T>,
+}
+
+struct Params(HashMap<String, String>);
+
+impl Params {
+    fn parse(query:&str) -> Params {
+        let decode = |s:&str| percent_decode_str(&s.replace('+', " ")).decode_utf8_lossy().into_owned();
+        Params(query.split('&').filter(|pair| !pair.is_empty()).map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (decode(key), decode(value)),
+            None => (decode(pair), String::new()),
+        }).collect())
+    }
+
+    fn text(&self, key:&str) -> Option<&str> { self.0.get(key).map(String::as_str).filter(|value| !value.is_empty()) }
+
+    // `?song`, `?song=1` and `?song=true` all turn a flag on
+    fn flag(&self, key:&str) -> Result<bool, Reply> {
+        match self.0.get(key).map(String::as_str) {
+            None | Some("0") | Some("false") => Ok(false),
+            Some("") | Some("1") | Some("true") => Ok(true),
+            Some(other) => Err(Reply::error(400, format!("`{key}` should be true or false, got [{other}]"))),
+        }
+    }
+
+    fn number(&self, key:&str, default:usize) -> Result<usize, Reply> {
+        match self.text(key) {
+            None => Ok(default),
+            Some(value) => value.parse().ok().filter(|&n| n > 0)
+                .ok_or_else(|| Reply::error(400, format!("`{key}` should be a positive number, got [{value}]"))),
+        }
+    }
+
+    fn date(&self, key:&str) -> Result<Option<String>, Reply> {
+        match self.text(key) {
+            None => Ok(None),
+            Some(value) => NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|date| Some(date.to_string()))
+                .map_err(|_| Reply::error(400, format!("`{key}` should be a YYYY-MM-DD date, got [{value}]"))),
+        }
+    }
+
+    // (page, per_page), with per_page capped
+    fn page(&self) -> Result<(usize, usize), Reply> {
+        Ok((self.number("page", 1)?, self.number("per_page", DEFAULT_PER_PAGE)?.min(MAX_PER_PAGE)))
+    }
+}
+
+// How many results come before `page`
+fn offset(page:usize, per_page:usize) -> usize { (page - 1).saturating_mul(per_page) }
+
+// `results` are fetched with one extra to know if there's a next page
+fn page_of<T:Serialize>(mut results:Vec<T>, page:usize, per_page:usize) -> Value {
+    let has_more = results.len() > per_page;
+    results.truncate(per_page);
+    json!(Page { page, per_page, has_more, results })
+}
+
+fn search(store:&mut dyn VoxStore, params:&Params) -> Result<Reply, Reply> {
+    let terms = filters::search_terms(params.text("q").unwrap_or_default());
+    if terms.is_empty() {
+        return Err(Reply::error(400, "`q` has nothing to search for once normalized"));
+    }
+    let (page, per_page) = params.page()?;
+    let query = SearchQuery {
+        terms,
+        song: params.flag("song")?,
+        morshu: params.flag("morshu")?,
+        grant: params.flag("grant")?,
+        author: params.text("author").map(str::to_string),
+        since: params.date("since")?,
+        until: params.date("until")?,
+        limit: per_page + 1,
+        offset: offset(page, per_page),
+    };
+    let mut body = page_of(store.search(&query).map_err(internal)?, page, per_page);
+    body["terms"] = json!(query.terms);
+    Ok(Reply::ok(body))
+}
+
+fn log(store:&mut dyn VoxStore, log_id:&str, params:&Params) -> Result<Reply, Reply> {
+    let (page, per_page) = params.page()?;
+    let voxes = store.log_page(log_id, per_page + 1, offset(page, per_page)).map_err(internal)?;
+    // Past the last page of a log is an empty page, not a missing log
+    if voxes.is_empty() && !store.has_log(log_id).map_err(internal)? {
+        return Err(Reply::error(404, format!("no log [{log_id}]")));
+    }
+    let mut body = page_of(voxes, page, per_page);
+    body["log_id"] = json!(log_id);
+    Ok(Reply::ok(body))
+}
+
+fn internal(e:VoxError) -> Reply {
+    error!(error = %e, "Request failed");
+    Reply::error(500, "internal error")
+}
+
+// Answers one request, `url` is the path and query string as sent
+pub fn handle(store:&mut dyn VoxStore, method:&Method, url:&str) -> Reply {
+    if *method != Method::Get {
+        return Reply::error(405, "only GET is supported");
+    }
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let params = Params::parse(query);
+    let segments : Vec<String> = path.split('/').filter(|s| !s.is_empty())
+        .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned()).collect();
+    let segments : Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    let reply = match segments.as_slice() {
+        ["health"] => match store.schema_version() {
+            Ok(version) => Ok(Reply::ok(json!({ "status": "ok", "schema_version": version }))),
+            Err(e) => {
+                error!(error = %e, "Health check failed");
+                Ok(Reply { status: 503, body: json!({ "status": "unavailable" }) })
+            },
+        },
+        ["search"] => search(store, &params),
+        ["vox", id] => match id.parse::<u64>() {
+            Err(_) => Err(Reply::error(400, format!("vox id should be a number, got [{id}]"))),
+            Ok(id) => match store.vox(id) {
+                Ok(Some(vox)) => Ok(Reply::ok(json!(vox))),
+                Ok(None) => Err(Reply::error(404, format!("no vox [{id}]"))),
+                Err(e) => Err(internal(e)),
+            },
+        },
+        ["log", log_id] => log(store, log_id, &params),
+        ["authors"] => params.page().and_then(|(page, per_page)| match store.authors(per_page + 1, offset(page, per_page)) {
+            Ok(authors) => Ok(Reply::ok(page_of(authors, page, per_page))),
+            Err(e) => Err(internal(e)),
+        }),
+        _ => Err(Reply::error(404, format!("nothing at [{path}]"))),
+    };
+    reply.unwrap_or_else(|e| e)
+}
+
+pub fn serve(bind:&str, pool:&dyn StorePool) -> VoxResult<()> {
+    let server = Server::http(bind).map_err(|e| VoxError::Config(format!("can't listen on [{bind}]: {e}")))?;
+    info!("Serving the vox search API on [http://{bind}], Ctrl-C to stop.");
+    let content_type = Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+    for request in server.incoming_requests() {
+        let reply = match pool.get() {
+            Ok(mut store) => handle(store.as_mut(), request.method(), request.url()),
+            Err(e) => {
+                error!(error = %e, "Can't reach the database");
+                Reply { status: 503, body: json!({ "status": "unavailable" }) }
+            },
+        };
+        info!(method = %request.method(), url = request.url(), status = reply.status, "Request");
+        let response = Response::from_string(reply.body.to_string())
+            .with_status_code(reply.status)
+            .with_header(content_type.clone());
+        if let Err(e) = request.respond(response) {
+            warn!(error = %e, "Couldn't send the response");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{self, VoxEntry, VoxIndexData};
+
+    fn test_store() -> Box<dyn VoxStore> {
+        let mut store = store::open("sqlite://:memory:").unwrap();
+        store.migrate().unwrap();
+        let voxes : Vec<VoxEntry> = [("alice", "^song n1 hello"), ("bob", "hello world"), ("alice", "attention")].iter().enumerate()
+            .map(|(position, (author, content))| VoxEntry {
+                id: 0,
+                author: author.to_string(),
+                log_id: "2021-07-24-api log.txt".to_string(),
+                position: position as u32,
+                date: "2021-07-24".to_string(),
+                content: content.to_string(),
+                content_hash: store::content_hash(content),
+                source: Some("test".to_string()),
+            }).collect();
+        store.upsert_voxes(&voxes).unwrap();
+        store.upsert_index(&[
+            VoxIndexData { id: 1, indexed_content: "cnote hello ".to_string(), has_song: true, has_morshu: false, has_grant: false, vocab_version: "v1".to_string() },
+            VoxIndexData { id: 2, indexed_content: "hello world ".to_string(), has_song: false, has_morshu: false, has_grant: false, vocab_version: "v1".to_string() },
+            VoxIndexData { id: 3, indexed_content: "attention ".to_string(), has_song: false, has_morshu: false, has_grant: false, vocab_version: "v1".to_string() },
+        ]).unwrap();
+        store
+    }
+
+    fn get(store:&mut Box<dyn VoxStore>, url:&str) -> Reply { handle(store.as_mut(), &Method::Get, url) }
+
+    #[test]
+    fn health_reports_the_schema() {
+        let mut store = test_store();
+        let reply = get(&mut store, "/health");
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body["schema_version"], store.latest_schema_version());
+    }
+
+    #[test]
+    fn search_normalizes_filters_and_pages() {
+        let mut store = test_store();
+        let reply = get(&mut store, "/search?q=N1");
+        assert_eq!(reply.body["terms"], json!(["cnote"]));
+        assert_eq!(reply.body["results"][0]["author"], "alice");
+
+        let reply = get(&mut store, "/search?q=hello&per_page=1");
+        assert_eq!(reply.body["results"].as_array().unwrap().len(), 1);
+        assert_eq!(reply.body["has_more"], true);
+        let reply = get(&mut store, "/search?q=hello&per_page=1&page=2");
+        assert_eq!(reply.body["has_more"], false);
+
+        let reply = get(&mut store, "/search?q=hello&song");
+        assert_eq!(reply.body["results"].as_array().unwrap().len(), 1);
+        let reply = get(&mut store, "/search?q=hello&author=bob");
+        assert_eq!(reply.body["results"][0]["content"], "hello world");
+
+        assert_eq!(get(&mut store, "/search?q=%2C").status, 400);
+        assert_eq!(get(&mut store, "/search?q=hello&since=yesterday").status, 400);
+        assert_eq!(get(&mut store, "/search?q=hello&page=0").status, 400);
+    }
+
+    #[test]
+    fn voxes_logs_and_authors() {
+        let mut store = test_store();
+        assert_eq!(get(&mut store, "/vox/2").body["author"], "bob");
+        assert_eq!(get(&mut store, "/vox/99").status, 404);
+        assert_eq!(get(&mut store, "/vox/two").status, 400);
+
+        let reply = get(&mut store, "/log/2021-07-24-api%20log.txt");
+        assert_eq!(reply.body["results"].as_array().unwrap().len(), 3);
+        assert_eq!(get(&mut store, "/log/nope.txt").status, 404);
+        let reply = get(&mut store, "/log/2021-07-24-api%20log.txt?per_page=2&page=2");
+        assert_eq!((reply.body["results"][0]["content"].as_str(), reply.body["has_more"].as_bool()), (Some("attention"), Some(false)));
+        let reply = get(&mut store, "/log/2021-07-24-api%20log.txt?page=3");
+        assert_eq!((reply.status, reply.body["results"].as_array().unwrap().len()), (200, 0));
+
+        let reply = get(&mut store, "/authors");
+        assert_eq!(reply.body["results"], json!([{ "author": "alice", "voxes": 2 }, { "author": "bob", "voxes": 1 }]));
+        let reply = get(&mut store, "/authors?per_page=1");
+        assert_eq!(reply.body["results"], json!([{ "author": "alice", "voxes": 2 }]));
+        assert_eq!(reply.body["has_more"], true);
+
+        assert_eq!(get(&mut store, "/nothing").status, 404);
+        assert_eq!(handle(store.as_mut(), &Method::Post, "/health").status, 405);
+    }
+}
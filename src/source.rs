@@ -0,0 +1,426 @@
+// Where vox logs are pulled from. Every source lists logs named YYYY-MM-DD-*.txt and hands back
+// their text, and what it was opened from is recorded on the voxes it produced.
+//
+//   https://rook.zone/voxlogs            an Apache-style directory index, what the crawler always read
+//   https://mirror/voxlogs/index.json    a JSON manifest, over HTTP or as a local file
+//   /backups/voxlogs                     a local directory of .txt logs
+//   /backups/voxlogs.tar.gz              a tarball (.tar, .tar.gz, .tgz) or a .zip of them
+//
+// A manifest looks like `{ "logs": [{ "id": "2021-07-24-voxlog.txt", "url": "logs/2021-07-24-voxlog.txt" }] }`,
+// where `url` is a URL or path, relative ones resolved against the manifest's own location, and
+// defaults to the id.
+
+use chrono::NaiveDate;
+use flate2::read::GzDecoder;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::header::{self, HeaderMap};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::error::{VoxError, VoxResult};
+use crate::http::{HttpClient, HttpSettings};
+use crate::store::LogState;
+
+pub struct Listing {
+    pub id: String,
+    pub date: String,
+}
+impl fmt::Display for Listing {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result { write!(f, "File:[{}] Date:[{}]", self.id, self.date) }
+}
+
+impl Listing {
+    // A listing for a log named YYYY-MM-DD-*.txt, dated by its name. Sources join the name onto their
+    // URL or directory, so one that could lead outside of it isn't a log.
+    pub fn from_name(name:&str) -> VoxResult<Listing> {
+        if name.contains(['/', '\\']) || name.contains("..") {
+            return Err(VoxError::Parse(format!("[{name}] isn't a plain file name")));
+        }
+        Ok(Listing {
+            id: name.to_string(),
+            date: parse_date_from_filename(name)?.format("%Y-%m-%d").to_string(),
+        })
+    }
+}
+
+pub fn parse_date_from_filename(name:&str) -> VoxResult<NaiveDate> {
+    let bad_name = || VoxError::Parse(format!("[{name}] doesn't start with a YYYY-MM-DD date"));
+    let split_name :Vec<&str> = name.split('-').collect();
+    if split_name.len() < 3 {
+        return Err(bad_name());
+    }
+    let year = split_name[0].parse().map_err(|_| bad_name())?;
+    let month = split_name[1].parse().map_err(|_| bad_name())?;
+    let day = split_name[2].parse().map_err(|_| bad_name())?;
+
+    NaiveDate::from_ymd_opt(year, month, day).ok_or_else(bad_name)
+}
+
+// Listings for the names that are logs, skipping (and saying so) the ones that aren't dated
+fn listings<'a>(names:impl IntoIterator<Item = &'a str>) -> Vec<Listing> {
+    names.into_iter().filter_map(|name| match Listing::from_name(name) {
+        Ok(listing) => Some(listing),
+        Err(e) => {
+            warn!(name, error = %e, "Skipping listing");
+            None
+        },
+    }).collect()
+}
+
+pub enum Fetched {
+    NotModified,
+    Body { text: String, etag: Option<String>, last_modified: Option<String> },
+}
+
+impl Fetched {
+    fn text(text:String) -> Fetched { Fetched::Body { text, etag: None, last_modified: None } }
+}
+
+pub trait LogSource: Send + Sync {
+    // What the source was opened from, stored on each vox it produced
+    fn name(&self) -> &str;
+    fn list(&self) -> VoxResult<Vec<Listing>>;
+    // `state` is how the log looked when it was last crawled, for sources that can tell it hasn't changed
+    fn fetch(&self, listing:&Listing, state:Option<&LogState>) -> VoxResult<Fetched>;
+}
+
+// Opens whichever kind of source `spec` names, see the top of this file. Sources on the web fetch
+// with `http`.
+pub fn open(spec:&str, http:&HttpSettings) -> VoxResult<Box<dyn LogSource>> {
+    let lower = spec.to_ascii_lowercase();
+    if lower.ends_with(".json") {
+        Ok(Box::new(Manifest::open(spec, http)?))
+    }
+    else if is_url(spec) {
+        Ok(Box::new(HttpIndex::new(spec, http)?))
+    }
+    else if lower.ends_with(".zip") || lower.ends_with(".tar") || lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+        Ok(Box::new(Archive::open(Path::new(spec))?))
+    }
+    else if Path::new(spec).is_dir() {
+        Ok(Box::new(LocalDir::new(Path::new(spec))))
+    }
+    else {
+        Err(VoxError::Config(format!("log source [{spec}] isn't a URL, a .json manifest, an archive or a directory")))
+    }
+}
+
+fn is_url(spec:&str) -> bool { spec.starts_with("http://") || spec.starts_with("https://") }
+
+fn fetch_text(client:&HttpClient, url:&str) -> VoxResult<String> {
+    Ok(client.get(url, HeaderMap::new())?.text)
+}
+
+// Conditional GET against what the log looked like last crawl, if we have crawled it
+fn fetch_log(client:&HttpClient, url:&str, state:Option<&LogState>) -> VoxResult<Fetched> {
+    let mut headers = HeaderMap::new();
+    let mut condition = |name, value:Option<&str>| {
+        if let Some(value) = value.and_then(|value| header::HeaderValue::from_str(value).ok()) {
+            headers.insert(name, value);
+        }
+    };
+    condition(header::IF_NONE_MATCH, state.and_then(|state| state.etag.as_deref()));
+    condition(header::IF_MODIFIED_SINCE, state.and_then(|state| state.last_modified.as_deref()));
+    let reply = client.get(url, headers)?;
+    if reply.status == StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::NotModified);
+    }
+    let header_str = |name| reply.headers.get(name).and_then(|value:&header::HeaderValue| value.to_str().ok()).map(str::to_string);
+    let etag = header_str(header::ETAG);
+    let last_modified = header_str(header::LAST_MODIFIED);
+    Ok(Fetched::Body { text: reply.text, etag, last_modified })
+}
+
+fn read_file(path:&Path) -> VoxResult<String> {
+    fs::read_to_string(path).map_err(|e| VoxError::file(path, e))
+}
+
+lazy_static! { static ref LISTING_RX: Regex = Regex::new(r#"<a href="([0-9]{4}-[0-9]{2}-[0-9]{2}-[^"/\\]*\.txt)">"#).unwrap(); }
+
+// An Apache-style directory index, like rook.zone's
+pub struct HttpIndex {
+    url: String,
+    client: HttpClient,
+}
+
+impl HttpIndex {
+    pub fn new(url:&str, http:&HttpSettings) -> VoxResult<HttpIndex> {
+        Ok(HttpIndex { url: url.trim_end_matches('/').to_string(), client: HttpClient::new(http)? })
+    }
+}
+
+impl LogSource for HttpIndex {
+    fn name(&self) -> &str { &self.url }
+
+    fn list(&self) -> VoxResult<Vec<Listing>> {
+        let root_body = fetch_text(&self.client, &self.url)?;
+        // Get all the entries from the root listing page
+        Ok(listings(LISTING_RX.captures_iter(&root_body).map(|cap| cap.get(1).map_or("", |m| m.as_str()))))
+    }
+
+    fn fetch(&self, listing:&Listing, state:Option<&LogState>) -> VoxResult<Fetched> {
+        fetch_log(&self.client, &format!("{}/{}", self.url, listing.id), state)
+    }
+}
+
+// A directory of log files, only the .txt files directly in it are listed
+pub struct LocalDir {
+    dir: PathBuf,
+    name: String,
+}
+
+impl LocalDir {
+    pub fn new(dir:&Path) -> LocalDir {
+        LocalDir { dir: dir.to_path_buf(), name: dir.display().to_string() }
+    }
+}
+
+impl LogSource for LocalDir {
+    fn name(&self) -> &str { &self.name }
+
+    fn list(&self) -> VoxResult<Vec<Listing>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.dir).map_err(|e| VoxError::file(&self.dir, e))? {
+            let path = entry.map_err(|e| VoxError::file(&self.dir, e))?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "txt") {
+                names.extend(path.file_name().and_then(|name| name.to_str()).map(str::to_string));
+            }
+        }
+        names.sort();
+        Ok(listings(names.iter().map(String::as_str)))
+    }
+
+    // No headers to go on, the crawl falls back on comparing hashes
+    fn fetch(&self, listing:&Listing, _state:Option<&LogState>) -> VoxResult<Fetched> {
+        read_file(&self.dir.join(&listing.id)).map(Fetched::text)
+    }
+}
+
+// A .tar, .tar.gz, .tgz or .zip of log files, read into memory when opened. Logs are listed by
+// file name wherever they are in the archive.
+pub struct Archive {
+    name: String,
+    logs: BTreeMap<String, String>,
+}
+
+impl Archive {
+    pub fn open(path:&Path) -> VoxResult<Archive> {
+        let on_err = |e| VoxError::file(path, e);
+        let file = File::open(path).map_err(on_err)?;
+        let mut logs = BTreeMap::new();
+        let mut add = |entry_path:&str, reader:&mut dyn Read| -> VoxResult<()> {
+            let Some(name) = entry_path.rsplit('/').next().filter(|name| name.ends_with(".txt")) else {
+                return Ok(());
+            };
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).map_err(on_err)?;
+            if logs.insert(name.to_string(), String::from_utf8_lossy(&bytes).into_owned()).is_some() {
+                warn!(archive = %path.display(), name, "Archive has more than one log by that name, using the one at [{entry_path}]");
+            }
+            Ok(())
+        };
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip")) {
+            let mut zip = zip::ZipArchive::new(file)
+                .map_err(|e| VoxError::Parse(format!("[{}] isn't a zip archive: {e}", path.display())))?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i)
+                    .map_err(|e| VoxError::Parse(format!("[{}] has a broken entry: {e}", path.display())))?;
+                if entry.is_file() {
+                    let entry_path = entry.name().to_string();
+                    add(&entry_path, &mut entry)?;
+                }
+            }
+        }
+        else {
+            let lower = path.to_string_lossy().to_ascii_lowercase();
+            let reader : Box<dyn Read> = if lower.ends_with(".gz") || lower.ends_with(".tgz") { Box::new(GzDecoder::new(file)) } else { Box::new(file) };
+            let mut tar = tar::Archive::new(reader);
+            for entry in tar.entries().map_err(on_err)? {
+                let mut entry = entry.map_err(on_err)?;
+                if entry.header().entry_type().is_file() {
+                    let entry_path = entry.path().map_err(on_err)?.to_string_lossy().into_owned();
+                    add(&entry_path, &mut entry)?;
+                }
+            }
+        }
+        Ok(Archive { name: path.display().to_string(), logs })
+    }
+}
+
+impl LogSource for Archive {
+    fn name(&self) -> &str { &self.name }
+
+    fn list(&self) -> VoxResult<Vec<Listing>> {
+        Ok(listings(self.logs.keys().map(String::as_str)))
+    }
+
+    fn fetch(&self, listing:&Listing, _state:Option<&LogState>) -> VoxResult<Fetched> {
+        self.logs.get(&listing.id).cloned().map(Fetched::text)
+            .ok_or_else(|| VoxError::NotFound(format!("[{}] in [{}]", listing.id, self.name)))
+    }
+}
+
+#[derive(Deserialize)]
+struct ManifestFile {
+    logs: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize)]
+struct ManifestEntry {
+    id: String,
+    url: Option<String>,
+}
+
+// A JSON list of logs and where each one is, read when opened
+pub struct Manifest {
+    name: String,
+    // Log id to URL or path, already resolved against the manifest's location
+    logs: BTreeMap<String, String>,
+    order: Vec<String>,
+    client: HttpClient,
+}
+
+impl Manifest {
+    pub fn open(spec:&str, http:&HttpSettings) -> VoxResult<Manifest> {
+        let client = HttpClient::new(http)?;
+        let text = if is_url(spec) { fetch_text(&client, spec)? } else { read_file(Path::new(spec))? };
+        let parsed : ManifestFile = serde_json::from_str(&text)
+            .map_err(|e| VoxError::Parse(format!("[{spec}] isn't a log manifest: {e}")))?;
+        // Relative locations are next to the manifest
+        let base = if is_url(spec) { spec.rsplit_once('/').map_or(spec, |(base, _)| base).to_string() }
+            else { Path::new(spec).parent().map_or(String::new(), |dir| dir.display().to_string()) };
+        let mut logs = BTreeMap::new();
+        let mut order = Vec::new();
+        for entry in parsed.logs {
+            let location = entry.url.unwrap_or_else(|| entry.id.clone());
+            let location = if is_url(&location) || Path::new(&location).is_absolute() { location }
+                else if is_url(spec) { format!("{base}/{location}") }
+                else { Path::new(&base).join(&location).display().to_string() };
+            if logs.insert(entry.id.clone(), location).is_none() {
+                order.push(entry.id);
+            }
+        }
+        Ok(Manifest { name: spec.to_string(), logs, order, client })
+    }
+}
+
+impl LogSource for Manifest {
+    fn name(&self) -> &str { &self.name }
+
+    fn list(&self) -> VoxResult<Vec<Listing>> {
+        Ok(listings(self.order.iter().map(String::as_str)))
+    }
+
+    fn fetch(&self, listing:&Listing, state:Option<&LogState>) -> VoxResult<Fetched> {
+        let location = self.logs.get(&listing.id)
+            .ok_or_else(|| VoxError::NotFound(format!("[{}] in [{}]", listing.id, self.name)))?;
+        if is_url(location) { fetch_log(&self.client, location, state) } else { read_file(Path::new(location)).map(Fetched::text) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const LOG: &str = "From alice: 12:00\nhello world\n";
+
+    fn open_local(spec:&str) -> VoxResult<Box<dyn LogSource>> { open(spec, &HttpSettings::default()) }
+
+    fn ids(source:&dyn LogSource) -> Vec<String> { source.list().unwrap().into_iter().map(|listing| listing.id).collect() }
+
+    fn text(source:&dyn LogSource, id:&str) -> String {
+        match source.fetch(&Listing::from_name(id).unwrap(), None).unwrap() {
+            Fetched::Body { text, .. } => text,
+            Fetched::NotModified => panic!("[{id}] came back not modified"),
+        }
+    }
+
+    #[test]
+    fn local_dirs_list_dated_txt_files() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        fs::write(dir.join("2021-07-25-b.txt"), LOG).unwrap();
+        fs::write(dir.join("2021-07-24-a.txt"), LOG).unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fs::write(dir.join("2021-07-26-c.md"), "").unwrap();
+        let source = open_local(&dir.display().to_string()).unwrap();
+        assert_eq!(ids(source.as_ref()), ["2021-07-24-a.txt", "2021-07-25-b.txt"]);
+        assert_eq!(text(source.as_ref(), "2021-07-25-b.txt"), LOG);
+        assert_eq!(source.list().unwrap()[0].date, "2021-07-24");
+    }
+
+    #[test]
+    fn archives_list_logs_wherever_they_are() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        let zip_path = dir.join("logs.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        zip.start_file("voxlogs/2021-07-24-a.txt", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(LOG.as_bytes()).unwrap();
+        zip.start_file("README", zip::write::FileOptions::default()).unwrap();
+        zip.finish().unwrap();
+
+        let tgz_path = dir.join("logs.tar.gz");
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(File::create(&tgz_path).unwrap(), flate2::Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(LOG.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, "backup/2021-07-25-b.txt", LOG.as_bytes()).unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let zip = open_local(&zip_path.display().to_string()).unwrap();
+        assert_eq!(ids(zip.as_ref()), ["2021-07-24-a.txt"]);
+        assert_eq!(text(zip.as_ref(), "2021-07-24-a.txt"), LOG);
+        let tgz = open_local(&tgz_path.display().to_string()).unwrap();
+        assert_eq!(ids(tgz.as_ref()), ["2021-07-25-b.txt"]);
+        assert_eq!(text(tgz.as_ref(), "2021-07-25-b.txt"), LOG);
+        assert!(tgz.fetch(&Listing::from_name("2021-07-24-a.txt").unwrap(), None).is_err());
+    }
+
+    #[test]
+    fn manifests_resolve_relative_locations() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        fs::create_dir_all(dir.join("logs")).unwrap();
+        fs::write(dir.join("logs").join("2021-07-24-a.txt"), LOG).unwrap();
+        fs::write(dir.join("2021-07-25-b.txt"), "From bob: 12:01\nattention\n").unwrap();
+        fs::write(dir.join("index.json"), r#"{ "logs": [
+            { "id": "2021-07-25-b.txt" },
+            { "id": "2021-07-24-a.txt", "url": "logs/2021-07-24-a.txt" },
+            { "id": "undated.txt" }
+        ] }"#).unwrap();
+        let source = open_local(&dir.join("index.json").display().to_string()).unwrap();
+        assert_eq!(ids(source.as_ref()), ["2021-07-25-b.txt", "2021-07-24-a.txt"]);
+        assert_eq!(text(source.as_ref(), "2021-07-24-a.txt"), LOG);
+        assert!(text(source.as_ref(), "2021-07-25-b.txt").contains("attention"));
+    }
+
+    #[test]
+    fn specs_pick_the_kind_of_source() {
+        assert_eq!(open_local("https://rook.zone/voxlogs/").unwrap().name(), "https://rook.zone/voxlogs");
+        assert!(open_local("no/such/dir").is_err());
+        assert!(open_local("no/such/manifest.json").is_err());
+        assert!(Listing::from_name("voxlog.txt").is_err());
+        assert_eq!(Listing::from_name("2021-07-24-voxlog.txt").unwrap().date, "2021-07-24");
+    }
+
+    #[test]
+    fn names_that_leave_the_source_are_not_listed() {
+        for name in ["2021-07-24-../../x.txt", "2021-07-24-a/b.txt", "2021-07-24-..\\x.txt", "2021-07-24-..txt"] {
+            assert!(Listing::from_name(name).is_err(), "{name}");
+        }
+        let ids : Vec<String> = listings(["2021-07-24-../../x.txt", "2021-07-24-a.txt"]).into_iter().map(|listing| listing.id).collect();
+        assert_eq!(ids, ["2021-07-24-a.txt"]);
+        let index = r#"<a href="2021-07-24-a.txt">a</a> <a href="2021-07-25-b.txt">b</a> <a href="2021-07-26-../x.txt">x</a>"#;
+        let names : Vec<&str> = LISTING_RX.captures_iter(index).map(|cap| cap.get(1).unwrap().as_str()).collect();
+        assert_eq!(names, ["2021-07-24-a.txt", "2021-07-25-b.txt"]);
+    }
+}
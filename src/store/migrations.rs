@@ -0,0 +1,49 @@
+// Schema migrations, embedded so the binary can stand up a database on its own.
+// Each backend has its own list, kept in step by version number.
+
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+macro_rules! migration {
+    ($backend:literal, $version:literal, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            sql: include_str!(concat!("../../migrations/", $backend, "/", $name, ".sql")),
+        }
+    };
+}
+
+pub const MYSQL: &[Migration] = &[
+    migration!("mysql", 1, "0001_initial"),
+    migration!("mysql", 2, "0002_vox_identity"),
+    migration!("mysql", 3, "0003_log_state"),
+    migration!("mysql", 4, "0004_vocabulary"),
+    migration!("mysql", 5, "0005_vox_source"),
+    migration!("mysql", 6, "0006_crawl_jobs"),
+];
+
+pub const SQLITE: &[Migration] = &[
+    migration!("sqlite", 1, "0001_initial"),
+    migration!("sqlite", 2, "0002_vox_identity"),
+    migration!("sqlite", 3, "0003_log_state"),
+    migration!("sqlite", 4, "0004_vocabulary"),
+    migration!("sqlite", 5, "0005_vox_source"),
+    migration!("sqlite", 6, "0006_crawl_jobs"),
+];
+
+pub fn latest_version(migrations:&[Migration]) -> u32 {
+    migrations.last().map_or(0, |m| m.version)
+}
+
+// Splits a migration into single statements, for drivers that won't take a whole script at once
+pub fn statements(sql:&str) -> Vec<String> {
+    sql.split(";\n")
+        .map(|stmt| stmt.lines().filter(|line| !line.trim_start().starts_with("--")).collect::<Vec<_>>().join("\n"))
+        .map(|stmt| stmt.trim().trim_end_matches(';').to_string())
+        .filter(|stmt| !stmt.is_empty())
+        .collect()
+}
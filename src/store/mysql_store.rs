@@ -0,0 +1,311 @@
+use mysql::{params, Opts, Pool, PooledConn, TxOpts};
+use mysql::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::error::VoxResult;
+use crate::store::migrations::{self, MYSQL as MIGRATIONS};
+use crate::store::{content_hash as content_hash_of, AuthorSummary, plan_upsert, IngestReport, Job, JobListing, ListingStatus, LogState, SearchHit, SearchQuery, StorePool, StoredVox, Upsert, VoxEntry, VoxIndexData, VoxStore};
+
+const SCHEMA_VERSION_TABLE: &str = r"CREATE TABLE IF NOT EXISTS schema_version (
+    version INT UNSIGNED NOT NULL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    applied_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+)";
+
+const VOX_COLUMNS: &str = "`id`, `author`, `log_id`, `position`, CAST(`date` AS CHAR), `content`, `content_hash`, `source`";
+type VoxRow = (u64, String, String, u32, String, String, Option<String>, Option<String>);
+// `position`, `author`, `content_hash`, `content`, `source`, what `plan_upsert` compares against
+type StoredRow = (u32, String, Option<String>, String, Option<String>);
+
+fn vox_from_row((id, author, log_id, position, date, content, content_hash, source):VoxRow) -> VoxEntry {
+    VoxEntry {
+        id, author, log_id, position, date,
+        content_hash: content_hash.unwrap_or_else(|| content_hash_of(&content)),
+        content,
+        source,
+    }
+}
+
+const JOB_COLUMNS: &str = "`id`, `command`, `source`, CAST(`started_at` AS CHAR), `finished_at` IS NOT NULL";
+type JobRow = (u64, String, String, String, bool);
+
+fn job_from_row((id, command, source, started_at, finished):JobRow) -> Job {
+    Job { id, command, source, started_at, finished }
+}
+
+// The production store, `vox_meta` has a FULLTEXT index on `indexed_content`
+pub struct MysqlStore {
+    conn: PooledConn,
+}
+
+pub struct MysqlPool {
+    pool: Pool,
+}
+
+impl MysqlPool {
+    pub fn connect(url:&str) -> VoxResult<MysqlPool> {
+        Ok(MysqlPool { pool: Pool::new(Opts::from_url(url)?)? })
+    }
+}
+
+impl StorePool for MysqlPool {
+    fn get(&self) -> VoxResult<Box<dyn VoxStore>> {
+        Ok(Box::new(MysqlStore { conn: self.pool.get_conn()? }))
+    }
+}
+
+impl VoxStore for MysqlStore {
+    fn upsert_voxes(&mut self, voxes:&[VoxEntry]) -> VoxResult<IngestReport> {
+        let mut tx = self.conn.start_transaction(TxOpts::default())?;
+        let mut stored : HashMap<(String, u32), StoredVox> = HashMap::new();
+        let log_ids : HashSet<&str> = voxes.iter().map(|vox| vox.log_id.as_str()).collect();
+        for log_id in log_ids {
+            let rows : Vec<StoredRow> =
+                tx.exec("SELECT `position`, `author`, `content_hash`, `content`, `source` FROM `voxes` WHERE `log_id` = ?", (log_id,))?;
+            stored.extend(rows.into_iter().map(|(position, author, content_hash, content, source)|
+                ((log_id.to_string(), position), StoredVox { author, content_hash, content, source })));
+        }
+
+        let mut report = IngestReport::default();
+        let mut inserts = Vec::new();
+        let mut updates = Vec::new();
+        for vox in voxes {
+            match plan_upsert(vox, stored.get(&(vox.log_id.clone(), vox.position)), &mut report) {
+                Upsert::Insert => inserts.push(vox),
+                Upsert::Update => updates.push(vox),
+                Upsert::Skip => (),
+            }
+        }
+        let to_params = |p:&&VoxEntry| params!{
+            "author" => &p.author,
+            "log_id" => &p.log_id,
+            "position" => p.position,
+            "date" => &p.date,
+            "content" => &p.content,
+            "content_hash" => &p.content_hash,
+            "source" => &p.source,
+        };
+        tx.exec_batch(
+            r"INSERT INTO voxes (author, log_id, position, date, content, content_hash, source)
+            VALUES (:author, :log_id, :position, :date, :content, :content_hash, :source)",
+            inserts.iter().map(to_params))?;
+        tx.exec_batch(
+            r"UPDATE voxes SET author = :author, date = :date, content = :content, content_hash = :content_hash, source = :source
+            WHERE log_id = :log_id AND position = :position",
+            updates.iter().map(to_params))?;
+        tx.commit()?;
+        Ok(report)
+    }
+
+    fn truncate_log(&mut self, log_id:&str, len:u32) -> VoxResult<usize> {
+        let mut tx = self.conn.start_transaction(TxOpts::default())?;
+        tx.exec_drop("DELETE FROM `vox_meta` WHERE `id` IN (SELECT `id` FROM `voxes` WHERE `log_id` = ? AND `position` >= ?)", (log_id, len))?;
+        tx.exec_drop("DELETE FROM `voxes` WHERE `log_id` = ? AND `position` >= ?", (log_id, len))?;
+        let removed = tx.affected_rows() as usize;
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    fn has_log(&mut self, log_id:&str) -> VoxResult<bool> {
+        let result:Option<u32> = self.conn.exec_first("SELECT COUNT(*) FROM `voxes` WHERE `log_id` = ?", (log_id,))?;
+        Ok(result.is_some_and(|x| x > 0))
+    }
+
+    fn logs(&mut self) -> VoxResult<Vec<String>> {
+        Ok(self.conn.query("SELECT `log_id` FROM `voxes` GROUP BY `log_id` ORDER BY MIN(`date`), `log_id`")?)
+    }
+
+    fn voxes_for_log(&mut self, log_id:&str) -> VoxResult<Vec<VoxEntry>> {
+        let voxes = self.conn.exec_map(format!("SELECT {VOX_COLUMNS} FROM `voxes` WHERE `log_id` = ? ORDER BY `position`"), (log_id,), vox_from_row)?;
+        Ok(voxes)
+    }
+
+    fn log_page(&mut self, log_id:&str, limit:usize, offset:usize) -> VoxResult<Vec<VoxEntry>> {
+        let voxes = self.conn.exec_map(format!("SELECT {VOX_COLUMNS} FROM `voxes` WHERE `log_id` = ? ORDER BY `position` LIMIT ? OFFSET ?"),
+            (log_id, limit as u64, offset as u64), vox_from_row)?;
+        Ok(voxes)
+    }
+
+    fn vox(&mut self, id:u64) -> VoxResult<Option<VoxEntry>> {
+        let row : Option<VoxRow> = self.conn.exec_first(format!("SELECT {VOX_COLUMNS} FROM `voxes` WHERE `id` = ?"), (id,))?;
+        Ok(row.map(vox_from_row))
+    }
+
+    fn upsert_index(&mut self, rows:&[VoxIndexData]) -> VoxResult<()> {
+        self.conn.exec_batch(
+            r"REPLACE INTO vox_meta (id, indexed_content, has_song, has_morshu, has_grant, vocab_version)
+            VALUES (:id, :indexed_content, :has_song, :has_morshu, :has_grant, :vocab_version)",
+            rows.iter().map(|p| params!{
+                "id" => p.id,
+                "indexed_content" => &p.indexed_content,
+                "has_song" => p.has_song,
+                "has_morshu" => p.has_morshu,
+                "has_grant" => p.has_grant,
+                "vocab_version" => &p.vocab_version,
+             }))?;
+        Ok(())
+    }
+
+    fn index_for_log(&mut self, log_id:&str) -> VoxResult<Vec<VoxIndexData>> {
+        let rows = self.conn.exec_map(
+            r"SELECT m.`id`, m.`indexed_content`, m.`has_song`, m.`has_morshu`, m.`has_grant`, m.`vocab_version` FROM `vox_meta` m
+            JOIN `voxes` v ON v.`id` = m.`id` WHERE v.`log_id` = ? ORDER BY v.`position`", (log_id,),
+            |(id, indexed_content, has_song, has_morshu, has_grant, vocab_version):(u64, String, bool, bool, bool, Option<String>)| VoxIndexData {
+                id, indexed_content, has_song, has_morshu, has_grant,
+                vocab_version: vocab_version.unwrap_or_default(),
+            })?;
+        Ok(rows)
+    }
+
+    fn search(&mut self, query:&SearchQuery) -> VoxResult<Vec<SearchHit>> {
+        let terms = query.terms.join(" ");
+        let hits = self.conn.exec_map(
+            r"SELECT v.`id`, v.`author`, v.`log_id`, v.`position`, CAST(v.`date` AS CHAR), v.`content`, v.`content_hash`, v.`source`,
+                MATCH(m.`indexed_content`) AGAINST(:terms IN NATURAL LANGUAGE MODE) AS score
+            FROM `voxes` v JOIN `vox_meta` m ON m.`id` = v.`id`
+            WHERE MATCH(m.`indexed_content`) AGAINST(:terms IN NATURAL LANGUAGE MODE)
+                AND (NOT :song OR m.`has_song`) AND (NOT :morshu OR m.`has_morshu`) AND (NOT :grant OR m.`has_grant`)
+                AND (:author IS NULL OR v.`author` = :author)
+                AND (:since IS NULL OR v.`date` >= :since) AND (:until IS NULL OR v.`date` <= :until)
+            ORDER BY score DESC, v.`id` LIMIT :limit OFFSET :offset",
+            params!{
+                "terms" => &terms,
+                "song" => query.song,
+                "morshu" => query.morshu,
+                "grant" => query.grant,
+                "author" => &query.author,
+                "since" => &query.since,
+                "until" => &query.until,
+                "limit" => query.limit as u64,
+                "offset" => query.offset as u64,
+            },
+            |(id, author, log_id, position, date, content, content_hash, source, score):(u64, String, String, u32, String, String, Option<String>, Option<String>, f64)| SearchHit {
+                vox: vox_from_row((id, author, log_id, position, date, content, content_hash, source)),
+                score,
+            })?;
+        Ok(hits)
+    }
+
+    fn authors(&mut self, limit:usize, offset:usize) -> VoxResult<Vec<AuthorSummary>> {
+        let authors = self.conn.exec_map(
+            "SELECT `author`, COUNT(*) AS voxes FROM `voxes` GROUP BY `author` ORDER BY voxes DESC, `author` LIMIT ? OFFSET ?",
+            (limit as u64, offset as u64),
+            |(author, voxes)| AuthorSummary { author, voxes })?;
+        Ok(authors)
+    }
+
+    fn stale_logs(&mut self, vocab_version:&str) -> VoxResult<Vec<(String, usize)>> {
+        let logs = self.conn.exec(
+            r"SELECT v.`log_id`, COUNT(*) FROM `vox_meta` m JOIN `voxes` v ON v.`id` = m.`id`
+            WHERE m.`vocab_version` IS NULL OR m.`vocab_version` <> ?
+            GROUP BY v.`log_id` ORDER BY v.`log_id`", (vocab_version,))?;
+        Ok(logs)
+    }
+
+    fn vocabulary(&mut self) -> VoxResult<Vec<String>> {
+        Ok(self.conn.query("SELECT `word` FROM `vocabulary` ORDER BY `word`")?)
+    }
+
+    fn save_vocabulary(&mut self, words:&[String]) -> VoxResult<()> {
+        let mut tx = self.conn.start_transaction(TxOpts::default())?;
+        tx.query_drop("DELETE FROM `vocabulary`")?;
+        tx.exec_batch("INSERT IGNORE INTO `vocabulary` (`word`) VALUES (?)", words.iter().map(|word| (word,)))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn log_state(&mut self, log_id:&str) -> VoxResult<Option<LogState>> {
+        let state = self.conn.exec_first(
+            "SELECT `size`, `etag`, `last_modified`, `content_hash` FROM `vox_logs` WHERE `log_id` = ?", (log_id,))?;
+        Ok(state.map(|(size, etag, last_modified, content_hash)| LogState { log_id: log_id.to_string(), size, etag, last_modified, content_hash }))
+    }
+
+    fn save_log_state(&mut self, state:&LogState) -> VoxResult<()> {
+        self.conn.exec_drop(
+            r"INSERT INTO vox_logs (log_id, size, etag, last_modified, content_hash)
+            VALUES (:log_id, :size, :etag, :last_modified, :content_hash)
+            ON DUPLICATE KEY UPDATE size = VALUES(size), etag = VALUES(etag), last_modified = VALUES(last_modified),
+                content_hash = VALUES(content_hash), crawled_at = CURRENT_TIMESTAMP",
+            params!{
+                "log_id" => &state.log_id,
+                "size" => state.size,
+                "etag" => &state.etag,
+                "last_modified" => &state.last_modified,
+                "content_hash" => &state.content_hash,
+            })?;
+        Ok(())
+    }
+
+    fn create_job(&mut self, command:&str, source:&str, listings:&[(String, String)]) -> VoxResult<u64> {
+        let mut tx = self.conn.start_transaction(TxOpts::default())?;
+        tx.exec_drop("INSERT INTO crawl_jobs (command, source) VALUES (?, ?)", (command, source))?;
+        let job_id = tx.last_insert_id().unwrap_or_default();
+        tx.exec_batch(
+            "INSERT INTO crawl_job_listings (job_id, position, log_id, date, status) VALUES (?, ?, ?, ?, ?)",
+            listings.iter().enumerate().map(|(position, (log_id, date))| (job_id, position as u32, log_id, date, ListingStatus::Pending.as_str())))?;
+        tx.commit()?;
+        Ok(job_id)
+    }
+
+    fn set_listing_status(&mut self, job_id:u64, log_id:&str, status:ListingStatus) -> VoxResult<()> {
+        self.conn.exec_drop(
+            "UPDATE crawl_job_listings SET status = ?, error = NULL WHERE job_id = ? AND log_id = ?",
+            (status.as_str(), job_id, log_id))?;
+        Ok(())
+    }
+
+    fn set_listing_error(&mut self, job_id:u64, log_id:&str, error:&str) -> VoxResult<()> {
+        self.conn.exec_drop(
+            "UPDATE crawl_job_listings SET error = ? WHERE job_id = ? AND log_id = ?",
+            (error, job_id, log_id))?;
+        Ok(())
+    }
+
+    fn finish_job(&mut self, job_id:u64) -> VoxResult<()> {
+        self.conn.exec_drop("UPDATE crawl_jobs SET finished_at = CURRENT_TIMESTAMP WHERE id = ?", (job_id,))?;
+        Ok(())
+    }
+
+    fn job(&mut self, job_id:u64) -> VoxResult<Option<Job>> {
+        let row : Option<JobRow> = self.conn.exec_first(format!("SELECT {JOB_COLUMNS} FROM crawl_jobs WHERE id = ?"), (job_id,))?;
+        Ok(row.map(job_from_row))
+    }
+
+    fn unfinished_jobs(&mut self) -> VoxResult<Vec<Job>> {
+        Ok(self.conn.query_map(format!("SELECT {JOB_COLUMNS} FROM crawl_jobs WHERE finished_at IS NULL ORDER BY id"), job_from_row)?)
+    }
+
+    fn job_listings(&mut self, job_id:u64) -> VoxResult<Vec<JobListing>> {
+        let rows : Vec<(String, String, String, Option<String>)> = self.conn.exec(
+            "SELECT `log_id`, CAST(`date` AS CHAR), `status`, `error` FROM crawl_job_listings WHERE job_id = ? ORDER BY position", (job_id,))?;
+        rows.into_iter().map(|(log_id, date, status, error)| Ok(JobListing { log_id, date, status: ListingStatus::parse(&status)?, error })).collect()
+    }
+
+    // Only reads, so it works for a read-only user too
+    fn schema_version(&mut self) -> VoxResult<u32> {
+        let exists : Option<u64> = self.conn.query_first(
+            "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = 'schema_version'")?;
+        if exists.unwrap_or(0) == 0 {
+            return Ok(0);
+        }
+        let version:Option<Option<u32>> = self.conn.query_first("SELECT MAX(version) FROM schema_version")?;
+        Ok(version.flatten().unwrap_or(0))
+    }
+
+    fn migrate(&mut self) -> VoxResult<Vec<u32>> {
+        self.conn.query_drop(SCHEMA_VERSION_TABLE)?;
+        let current = self.schema_version()?;
+        let mut applied = Vec::new();
+        // MySQL commits DDL implicitly, so a migration that fails partway can't be rolled back and has to be finished by hand
+        for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+            for stmt in migrations::statements(migration.sql) {
+                self.conn.query_drop(stmt)?;
+            }
+            self.conn.exec_drop("INSERT INTO schema_version (version, name) VALUES (?, ?)", (migration.version, migration.name))?;
+            applied.push(migration.version);
+        }
+        Ok(applied)
+    }
+
+    fn latest_schema_version(&self) -> u32 { migrations::latest_version(MIGRATIONS) }
+}
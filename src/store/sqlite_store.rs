@@ -0,0 +1,313 @@
+use rusqlite::{params, Connection, OptionalExtension, Row, TransactionBehavior};
+use std::time::Duration;
+
+use crate::error::VoxResult;
+use crate::store::migrations::{self, SQLITE as MIGRATIONS};
+use crate::store::{content_hash, AuthorSummary, plan_upsert, IngestReport, Job, JobListing, ListingStatus, LogState, SearchHit, SearchQuery, StorePool, StoredVox, Upsert, VoxEntry, VoxIndexData, VoxStore};
+
+const SCHEMA_VERSION_TABLE: &str = r"CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+)";
+
+const VOX_COLUMNS: &str = "id, author, log_id, position, date, content, content_hash, source";
+
+fn vox_from_row(row:&Row) -> rusqlite::Result<VoxEntry> {
+    let content : String = row.get(5)?;
+    let hash : Option<String> = row.get(6)?;
+    Ok(VoxEntry {
+        id: row.get(0)?,
+        author: row.get(1)?,
+        log_id: row.get(2)?,
+        position: row.get(3)?,
+        date: row.get(4)?,
+        content_hash: hash.unwrap_or_else(|| content_hash(&content)),
+        content,
+        source: row.get(7)?,
+    })
+}
+
+const JOB_COLUMNS: &str = "id, command, source, started_at, finished_at IS NOT NULL";
+
+fn job_from_row(row:&Row) -> rusqlite::Result<Job> {
+    Ok(Job { id: row.get(0)?, command: row.get(1)?, source: row.get(2)?, started_at: row.get(3)?, finished: row.get(4)? })
+}
+
+// How long a connection waits on another one's write before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Embedded store for working offline, `path` can be `:memory:`
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    pub fn open(path:&str) -> VoxResult<SqliteStore> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(SqliteStore { conn })
+    }
+}
+
+// Connections to one file, writers take turns. Every `:memory:` connection is a database of its
+// own, so that only makes sense for a single one.
+pub struct SqlitePool {
+    path: String,
+}
+
+impl SqlitePool {
+    pub fn new(path:&str) -> SqlitePool { SqlitePool { path: path.to_string() } }
+}
+
+impl StorePool for SqlitePool {
+    fn get(&self) -> VoxResult<Box<dyn VoxStore>> {
+        Ok(Box::new(SqliteStore::open(&self.path)?))
+    }
+
+    fn max_connections(&self) -> Option<usize> { (self.path == ":memory:").then_some(1) }
+}
+
+impl VoxStore for SqliteStore {
+    fn upsert_voxes(&mut self, voxes:&[VoxEntry]) -> VoxResult<IngestReport> {
+        // Taking the write lock up front, a read that later upgrades can fail outright when another worker writes
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut report = IngestReport::default();
+        {
+            let mut select = tx.prepare("SELECT author, content_hash, content, source FROM voxes WHERE log_id = ?1 AND position = ?2")?;
+            let mut insert = tx.prepare(
+                "INSERT INTO voxes (author, log_id, position, date, content, content_hash, source) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+            let mut update = tx.prepare(
+                "UPDATE voxes SET author = ?1, date = ?4, content = ?5, content_hash = ?6, source = ?7 WHERE log_id = ?2 AND position = ?3")?;
+            for p in voxes {
+                let stored = select.query_row(params![p.log_id, p.position],
+                    |row| Ok(StoredVox { author: row.get(0)?, content_hash: row.get(1)?, content: row.get(2)?, source: row.get(3)? })).optional()?;
+                let values = params![p.author, p.log_id, p.position, p.date, p.content, p.content_hash, p.source];
+                match plan_upsert(p, stored.as_ref(), &mut report) {
+                    Upsert::Insert => { insert.execute(values)?; },
+                    Upsert::Update => { update.execute(values)?; },
+                    Upsert::Skip => (),
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(report)
+    }
+
+    fn truncate_log(&mut self, log_id:&str, len:u32) -> VoxResult<usize> {
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute("DELETE FROM vox_meta WHERE id IN (SELECT id FROM voxes WHERE log_id = ?1 AND position >= ?2)", params![log_id, len])?;
+        let removed = tx.execute("DELETE FROM voxes WHERE log_id = ?1 AND position >= ?2", params![log_id, len])?;
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    fn has_log(&mut self, log_id:&str) -> VoxResult<bool> {
+        let count : u32 = self.conn.query_row("SELECT COUNT(*) FROM voxes WHERE log_id = ?1", [log_id], |row| row.get(0))?;
+        Ok(count > 0)
+    }
+
+    fn logs(&mut self) -> VoxResult<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT log_id FROM voxes GROUP BY log_id ORDER BY MIN(date), log_id")?;
+        let logs = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<_>, _>>()?;
+        Ok(logs)
+    }
+
+    fn voxes_for_log(&mut self, log_id:&str) -> VoxResult<Vec<VoxEntry>> {
+        let mut stmt = self.conn.prepare(&format!("SELECT {VOX_COLUMNS} FROM voxes WHERE log_id = ?1 ORDER BY position"))?;
+        let voxes = stmt.query_map([log_id], vox_from_row)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(voxes)
+    }
+
+    fn log_page(&mut self, log_id:&str, limit:usize, offset:usize) -> VoxResult<Vec<VoxEntry>> {
+        let mut stmt = self.conn.prepare(&format!("SELECT {VOX_COLUMNS} FROM voxes WHERE log_id = ?1 ORDER BY position LIMIT ?2 OFFSET ?3"))?;
+        let voxes = stmt.query_map(params![log_id, limit as i64, offset as i64], vox_from_row)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(voxes)
+    }
+
+    fn vox(&mut self, id:u64) -> VoxResult<Option<VoxEntry>> {
+        let vox = self.conn.query_row(&format!("SELECT {VOX_COLUMNS} FROM voxes WHERE id = ?1"), [id], vox_from_row).optional()?;
+        Ok(vox)
+    }
+
+    fn upsert_index(&mut self, rows:&[VoxIndexData]) -> VoxResult<()> {
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        {
+            let mut stmt = tx.prepare(
+                r"INSERT INTO vox_meta (id, indexed_content, has_song, has_morshu, has_grant, vocab_version) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                ON CONFLICT (id) DO UPDATE SET indexed_content = excluded.indexed_content, has_song = excluded.has_song,
+                    has_morshu = excluded.has_morshu, has_grant = excluded.has_grant, vocab_version = excluded.vocab_version")?;
+            for p in rows {
+                stmt.execute(params![p.id, p.indexed_content, p.has_song, p.has_morshu, p.has_grant, p.vocab_version])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn index_for_log(&mut self, log_id:&str) -> VoxResult<Vec<VoxIndexData>> {
+        let mut stmt = self.conn.prepare(
+            r"SELECT vox_meta.id, indexed_content, has_song, has_morshu, has_grant, vocab_version FROM vox_meta
+            JOIN voxes ON voxes.id = vox_meta.id WHERE voxes.log_id = ?1 ORDER BY voxes.position")?;
+        let rows = stmt.query_map([log_id], |row| Ok(VoxIndexData {
+            id: row.get(0)?,
+            indexed_content: row.get(1)?,
+            has_song: row.get(2)?,
+            has_morshu: row.get(3)?,
+            has_grant: row.get(4)?,
+            vocab_version: row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+        }))?.collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    fn search(&mut self, query:&SearchQuery) -> VoxResult<Vec<SearchHit>> {
+        // Any term matches, like MySQL's natural language mode, with bm25 doing the ranking
+        let terms = query.terms.iter().map(|term| format!("\"{}\"", term.replace('"', "\"\""))).collect::<Vec<_>>().join(" OR ");
+        let mut stmt = self.conn.prepare(&format!(
+            r"SELECT {VOX_COLUMNS}, score FROM voxes JOIN (
+                SELECT vox_meta.id AS hit, -bm25(vox_meta_fts) AS score FROM vox_meta_fts JOIN vox_meta ON vox_meta.id = vox_meta_fts.rowid
+                WHERE vox_meta_fts MATCH ?1 AND (?2 = 0 OR has_song) AND (?3 = 0 OR has_morshu) AND (?4 = 0 OR has_grant)
+            ) ON hit = voxes.id
+            WHERE (?5 IS NULL OR author = ?5) AND (?6 IS NULL OR date >= ?6) AND (?7 IS NULL OR date <= ?7)
+            ORDER BY score DESC, id LIMIT ?8 OFFSET ?9"))?;
+        let hits = stmt.query_map(
+            params![terms, query.song, query.morshu, query.grant, query.author, query.since, query.until, query.limit as i64, query.offset as i64],
+            |row| Ok(SearchHit { vox: vox_from_row(row)?, score: row.get(8)? }))?.collect::<Result<Vec<_>, _>>()?;
+        Ok(hits)
+    }
+
+    fn authors(&mut self, limit:usize, offset:usize) -> VoxResult<Vec<AuthorSummary>> {
+        let mut stmt = self.conn.prepare("SELECT author, COUNT(*) AS voxes FROM voxes GROUP BY author ORDER BY voxes DESC, author LIMIT ?1 OFFSET ?2")?;
+        let authors = stmt.query_map(params![limit as i64, offset as i64], |row| Ok(AuthorSummary { author: row.get(0)?, voxes: row.get(1)? }))?.collect::<Result<Vec<_>, _>>()?;
+        Ok(authors)
+    }
+
+    fn stale_logs(&mut self, vocab_version:&str) -> VoxResult<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare(
+            r"SELECT voxes.log_id, COUNT(*) FROM vox_meta JOIN voxes ON voxes.id = vox_meta.id
+            WHERE vox_meta.vocab_version IS NULL OR vox_meta.vocab_version <> ?1
+            GROUP BY voxes.log_id ORDER BY voxes.log_id")?;
+        let logs = stmt.query_map([vocab_version], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<Vec<_>, _>>()?;
+        Ok(logs)
+    }
+
+    fn vocabulary(&mut self) -> VoxResult<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT word FROM vocabulary ORDER BY word")?;
+        let words = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<_>, _>>()?;
+        Ok(words)
+    }
+
+    fn save_vocabulary(&mut self, words:&[String]) -> VoxResult<()> {
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute("DELETE FROM vocabulary", [])?;
+        {
+            let mut stmt = tx.prepare("INSERT OR IGNORE INTO vocabulary (word) VALUES (?1)")?;
+            for word in words {
+                stmt.execute([word])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn log_state(&mut self, log_id:&str) -> VoxResult<Option<LogState>> {
+        let state = self.conn.query_row(
+            "SELECT size, etag, last_modified, content_hash FROM vox_logs WHERE log_id = ?1", [log_id],
+            |row| Ok(LogState {
+                log_id: log_id.to_string(),
+                size: row.get(0)?,
+                etag: row.get(1)?,
+                last_modified: row.get(2)?,
+                content_hash: row.get(3)?,
+            })).optional()?;
+        Ok(state)
+    }
+
+    fn save_log_state(&mut self, state:&LogState) -> VoxResult<()> {
+        self.conn.execute(
+            r"INSERT INTO vox_logs (log_id, size, etag, last_modified, content_hash) VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT (log_id) DO UPDATE SET size = excluded.size, etag = excluded.etag, last_modified = excluded.last_modified,
+                content_hash = excluded.content_hash, crawled_at = CURRENT_TIMESTAMP",
+            params![state.log_id, state.size, state.etag, state.last_modified, state.content_hash])?;
+        Ok(())
+    }
+
+    fn create_job(&mut self, command:&str, source:&str, listings:&[(String, String)]) -> VoxResult<u64> {
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute("INSERT INTO crawl_jobs (command, source) VALUES (?1, ?2)", params![command, source])?;
+        let job_id = tx.last_insert_rowid() as u64;
+        {
+            let mut stmt = tx.prepare("INSERT INTO crawl_job_listings (job_id, position, log_id, date, status) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            for (position, (log_id, date)) in listings.iter().enumerate() {
+                stmt.execute(params![job_id, position as u32, log_id, date, ListingStatus::Pending.as_str()])?;
+            }
+        }
+        tx.commit()?;
+        Ok(job_id)
+    }
+
+    fn set_listing_status(&mut self, job_id:u64, log_id:&str, status:ListingStatus) -> VoxResult<()> {
+        self.conn.execute(
+            "UPDATE crawl_job_listings SET status = ?3, error = NULL, updated_at = CURRENT_TIMESTAMP WHERE job_id = ?1 AND log_id = ?2",
+            params![job_id, log_id, status.as_str()])?;
+        Ok(())
+    }
+
+    fn set_listing_error(&mut self, job_id:u64, log_id:&str, error:&str) -> VoxResult<()> {
+        self.conn.execute(
+            "UPDATE crawl_job_listings SET error = ?3, updated_at = CURRENT_TIMESTAMP WHERE job_id = ?1 AND log_id = ?2",
+            params![job_id, log_id, error])?;
+        Ok(())
+    }
+
+    fn finish_job(&mut self, job_id:u64) -> VoxResult<()> {
+        self.conn.execute("UPDATE crawl_jobs SET finished_at = CURRENT_TIMESTAMP WHERE id = ?1", [job_id])?;
+        Ok(())
+    }
+
+    fn job(&mut self, job_id:u64) -> VoxResult<Option<Job>> {
+        let job = self.conn.query_row(&format!("SELECT {JOB_COLUMNS} FROM crawl_jobs WHERE id = ?1"), [job_id], job_from_row).optional()?;
+        Ok(job)
+    }
+
+    fn unfinished_jobs(&mut self) -> VoxResult<Vec<Job>> {
+        let mut stmt = self.conn.prepare(&format!("SELECT {JOB_COLUMNS} FROM crawl_jobs WHERE finished_at IS NULL ORDER BY id"))?;
+        let jobs = stmt.query_map([], job_from_row)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(jobs)
+    }
+
+    fn job_listings(&mut self, job_id:u64) -> VoxResult<Vec<JobListing>> {
+        let mut stmt = self.conn.prepare("SELECT log_id, date, status, error FROM crawl_job_listings WHERE job_id = ?1 ORDER BY position")?;
+        let rows = stmt.query_map([job_id], |row| Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?, row.get(3)?)))?
+            .collect::<Result<Vec<(String, String, String, Option<String>)>, _>>()?;
+        rows.into_iter().map(|(log_id, date, status, error)| Ok(JobListing { log_id, date, status: ListingStatus::parse(&status)?, error })).collect()
+    }
+
+    // Only reads, so it works for a read-only user too
+    fn schema_version(&mut self) -> VoxResult<u32> {
+        let exists : bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')", [], |row| row.get(0))?;
+        if !exists {
+            return Ok(0);
+        }
+        let version : Option<u32> = self.conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))?;
+        Ok(version.unwrap_or(0))
+    }
+
+    fn migrate(&mut self) -> VoxResult<Vec<u32>> {
+        self.conn.execute(SCHEMA_VERSION_TABLE, [])?;
+        let current = self.schema_version()?;
+        let mut applied = Vec::new();
+        for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+            // Each migration lands whole or not at all
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(migration.sql)?;
+            tx.execute("INSERT INTO schema_version (version, name) VALUES (?1, ?2)", params![migration.version, migration.name])?;
+            tx.commit()?;
+            applied.push(migration.version);
+        }
+        Ok(applied)
+    }
+
+    fn latest_schema_version(&self) -> u32 { migrations::latest_version(MIGRATIONS) }
+}
@@ -0,0 +1,264 @@
+// The words vox knows how to say, that indexed words are validated against.
+//
+// A vocabulary comes from a word list file (one word per line, like `vox_db.txt`), the copy of
+// `vox_db.txt` built into the binary, or the `vocabulary` table. Its version is a hash of the
+// words themselves, so the same list has the same version wherever it was loaded from.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+use crate::error::{VoxError, VoxResult};
+use crate::store::{self, VoxStore};
+
+const EMBEDDED: &str = include_str!("../vox_db.txt");
+const DEFAULT_PATH: &str = "vox_db.txt";
+// Past these a "compound" is more likely noise that happens to spell vocab words
+const MAX_COMPOUND_LEN: usize = 40;
+const MAX_PARTS: usize = 4;
+
+pub struct Vocabulary {
+    words: HashSet<String>,
+    version: String,
+    // Where it was loaded from, for reports
+    source: String,
+}
+
+impl Vocabulary {
+    pub fn from_words<I:IntoIterator<Item = String>>(words:I, source:&str) -> VoxResult<Vocabulary> {
+        let sorted : BTreeSet<String> = words.into_iter().map(|word| word.trim().to_string()).filter(|word| !word.is_empty()).collect();
+        if sorted.is_empty() {
+            return Err(VoxError::Vocabulary(format!("[{source}] has no words")));
+        }
+        let listing = sorted.iter().map(String::as_str).collect::<Vec<_>>().join("\n");
+        Ok(Vocabulary {
+            version: store::content_hash(&listing)[..12].to_string(),
+            words: sorted.into_iter().collect(),
+            source: source.to_string(),
+        })
+    }
+
+    pub fn from_path(path:&Path) -> VoxResult<Vocabulary> {
+        let text = fs::read_to_string(path).map_err(|e| VoxError::Vocabulary(format!("reading [{}] failed: {e}", path.display())))?;
+        Vocabulary::from_words(text.lines().map(str::to_string), &path.display().to_string())
+    }
+
+    pub fn embedded() -> Vocabulary {
+        Vocabulary::from_words(EMBEDDED.lines().map(str::to_string), "embedded").expect("the embedded vocabulary has words")
+    }
+
+    pub fn from_store(store:&mut dyn VoxStore) -> VoxResult<Vocabulary> {
+        Vocabulary::from_words(store.vocabulary()?, "db")
+    }
+
+    // `source` is a word list path, `embedded` or `db`. Without one, `vox_db.txt` in the working
+    // directory is used if it's there, the embedded list otherwise.
+    pub fn load(source:Option<&str>, store:&mut dyn VoxStore) -> VoxResult<Vocabulary> {
+        let vocab = match Vocabulary::load_local(source)? {
+            Some(vocab) => vocab,
+            None => Vocabulary::from_store(store)?,
+        };
+        info!(version = %vocab.version, source = %vocab.source, words = vocab.len(), "Using vocabulary");
+        Ok(vocab)
+    }
+
+    // Same as `load`, but `None` for `db` so callers only need a store when it's asked for
+    pub fn load_local(source:Option<&str>) -> VoxResult<Option<Vocabulary>> {
+        let vocab = match source {
+            Some("embedded") => Vocabulary::embedded(),
+            Some("db") => return Ok(None),
+            Some(path) => Vocabulary::from_path(Path::new(path))?,
+            None if Path::new(DEFAULT_PATH).exists() => Vocabulary::from_path(Path::new(DEFAULT_PATH))?,
+            None => Vocabulary::embedded(),
+        };
+        Ok(Some(vocab))
+    }
+
+    pub fn contains(&self, word:&str) -> bool { self.words.contains(word) }
+    pub fn version(&self) -> &str { &self.version }
+    pub fn len(&self) -> usize { self.words.len() }
+
+    // The closest words to one that isn't in the vocabulary, best first. The usual vox typos are
+    // tried before edit distance: a missing `warn`, a doubled or undoubled letter, and a pitch
+    // glued on either end (`2hello`, `hello+3`).
+    pub fn suggest(&self, word:&str, max:usize) -> Vec<String> {
+        let mut suggestions : Vec<String> = Vec::new();
+        let add = |candidate:String, suggestions:&mut Vec<String>| {
+            if candidate != word && self.contains(&candidate) && !suggestions.contains(&candidate) {
+                suggestions.push(candidate);
+            }
+        };
+        add(format!("{word}warn"), &mut suggestions);
+        add(word.trim_matches(|c:char| c.is_ascii_digit() || c == '+' || c == '-').to_string(), &mut suggestions);
+        let chars : Vec<char> = word.chars().collect();
+        for i in 0..chars.len() {
+            let mut undoubled = chars.clone();
+            if i + 1 < chars.len() && chars[i] == chars[i + 1] {
+                undoubled.remove(i);
+                add(undoubled.into_iter().collect(), &mut suggestions);
+            }
+            let mut doubled = chars.clone();
+            doubled.insert(i, chars[i]);
+            add(doubled.into_iter().collect(), &mut suggestions);
+        }
+
+        // Short words are one typo away from too much of the vocabulary to allow two
+        let limit = if chars.len() > 4 { 2 } else { 1 };
+        let mut close : Vec<(usize, &String)> = self.words.iter()
+            .filter(|candidate| candidate.chars().count().abs_diff(chars.len()) <= limit)
+            .map(|candidate| (edit_distance(word, candidate), candidate))
+            .filter(|&(distance, _)| distance <= limit)
+            .collect();
+        close.sort();
+        for (_, candidate) in close {
+            add(candidate.clone(), &mut suggestions);
+        }
+        suggestions.truncate(max);
+        suggestions
+    }
+
+    // The vocab entry for a piece of a compound, short words are stored padded the way
+    // `filters::pad_short_words` writes them. Single letters only count when they're words.
+    fn entry(&self, piece:&str) -> Option<String> {
+        let entry = match piece.len() {
+            1 if !matches!(piece, "a" | "i" | "o") => return None,
+            1 | 2 => format!("{piece:_<3}"),
+            _ => piece.to_string(),
+        };
+        self.contains(&entry).then_some(entry)
+    }
+
+    // Splits a word that isn't in the vocab into vocab entries, `afunny` into `a__` and `funny`.
+    // Longest matches first, and if that paints itself into a corner, the split with the fewest
+    // parts. Only plain lowercase words are tried, and never into more than MAX_PARTS.
+    pub fn decompose(&self, word:&str) -> Option<Vec<String>> {
+        if word.len() < 2 || word.len() > MAX_COMPOUND_LEN || !word.bytes().all(|b| b.is_ascii_lowercase()) {
+            return None;
+        }
+        self.decompose_greedy(word).or_else(|| self.decompose_fewest(word))
+            .filter(|parts| parts.len() >= 2 && parts.len() <= MAX_PARTS)
+    }
+
+    fn decompose_greedy(&self, word:&str) -> Option<Vec<String>> {
+        let mut parts = Vec::new();
+        let mut rest = word;
+        while !rest.is_empty() {
+            let (len, entry) = (1..=rest.len()).rev().find_map(|len| self.entry(&rest[..len]).map(|entry| (len, entry)))?;
+            parts.push(entry);
+            rest = &rest[len..];
+        }
+        Some(parts)
+    }
+
+    fn decompose_fewest(&self, word:&str) -> Option<Vec<String>> {
+        // best[i] is the fewest parts covering word[..i], as (parts, where the last one starts)
+        let mut best : Vec<Option<(usize, usize)>> = vec![None; word.len() + 1];
+        best[0] = Some((0, 0));
+        for end in 1..=word.len() {
+            best[end] = (0..end)
+                .filter_map(|start| best[start].map(|(parts, _)| (parts + 1, start)))
+                .filter(|&(_, start)| self.entry(&word[start..end]).is_some())
+                .min();
+        }
+        let mut parts = Vec::new();
+        let mut end = word.len();
+        while end > 0 {
+            let (_, start) = best[end]?;
+            parts.push(self.entry(&word[start..end])?);
+            end = start;
+        }
+        parts.reverse();
+        Some(parts)
+    }
+
+    // Sorted, for storing
+    pub fn words(&self) -> Vec<String> {
+        let mut words : Vec<String> = self.words.iter().cloned().collect();
+        words.sort();
+        words
+    }
+}
+
+// Levenshtein distance, by characters
+fn edit_distance(a:&str, b:&str) -> usize {
+    let b : Vec<char> = b.chars().collect();
+    let mut row : Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_depends_only_on_the_words() {
+        let a = Vocabulary::from_words(["hello", "world", "hello"].map(String::from), "a").unwrap();
+        let b = Vocabulary::from_words([" world", "hello", ""].map(String::from), "b").unwrap();
+        let c = Vocabulary::from_words(["hello"].map(String::from), "c").unwrap();
+        assert_eq!(a.version(), b.version());
+        assert_ne!(a.version(), c.version());
+        assert_eq!(a.len(), 2);
+        assert!(Vocabulary::from_words(Vec::new(), "empty").is_err());
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "vox"), 3);
+        assert_eq!(edit_distance("vox", "vox"), 0);
+        assert_eq!(edit_distance("vöx", "vox"), 1);
+    }
+
+    #[test]
+    fn suggestions_try_vox_typos_first() {
+        let vocab = Vocabulary::embedded();
+        assert_eq!(vocab.suggest("cheer", 3).first().map(String::as_str), Some("cheerwarn"));
+        assert_eq!(vocab.suggest("helllo", 3).first().map(String::as_str), Some("hello"));
+        assert_eq!(vocab.suggest("atention", 3).first().map(String::as_str), Some("attention"));
+        assert_eq!(vocab.suggest("+2hello-3", 3).first().map(String::as_str), Some("hello"));
+        assert_eq!(vocab.suggest("helo", 3).first().map(String::as_str), Some("hello"));
+        assert!(vocab.suggest("qqqqqqqqqq", 3).is_empty());
+        assert!(vocab.suggest("hello", 3).len() <= 3);
+    }
+
+    #[test]
+    fn compounds_split_into_vocab_words() {
+        let vocab = Vocabulary::embedded();
+        assert_eq!(vocab.decompose("agame"), Some(vec!["a__".to_string(), "game".to_string()]));
+        assert_eq!(vocab.decompose("funnybro"), Some(vec!["funny".to_string(), "bro".to_string()]));
+        // Never down to letters, or past MAX_PARTS
+        assert_eq!(vocab.decompose("xqzj"), None);
+        assert_eq!(vocab.decompose("funnyfunnyfunnyfunnyfunny"), None);
+        assert_eq!(vocab.decompose("funny2"), None);
+    }
+
+    #[test]
+    fn fewest_parts_when_greedy_gets_stuck() {
+        let vocab = Vocabulary::from_words(["abc", "abcd", "def"].map(String::from), "test").unwrap();
+        assert_eq!(vocab.decompose_greedy("abcdef"), None);
+        assert_eq!(vocab.decompose("abcdef"), Some(vec!["abc".to_string(), "def".to_string()]));
+    }
+
+    #[test]
+    fn embedded_matches_the_file_and_round_trips_through_the_db() {
+        let embedded = Vocabulary::embedded();
+        assert_eq!(embedded.version(), Vocabulary::from_path(Path::new(DEFAULT_PATH)).unwrap().version());
+        assert!(embedded.contains("hello"));
+
+        let mut store = store::open("sqlite://:memory:").unwrap();
+        store.migrate().unwrap();
+        assert!(Vocabulary::from_store(store.as_mut()).is_err());
+        store.save_vocabulary(&embedded.words()).unwrap();
+        assert_eq!(Vocabulary::load(Some("db"), store.as_mut()).unwrap().version(), embedded.version());
+    }
+}
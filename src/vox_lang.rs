@@ -0,0 +1,435 @@
+// Tokenizer and parser for vox markup, the language the `filters` in `vox_utils` strip apart.
+//
+//   vox      := command? node*
+//   command  := "!tc vox " | "!op vox "
+//   node     := word | repeat | pitch | control | pause | truncate | space | text
+//   word     := modifier? name modifier?          e.g. `+2want`, `n13-7`, `d1++`
+//   repeat   := modifier? "*" modifier?           the previous word again, e.g. `+4-*`, `*-7`
+//   control  := "^" name ("=" arg)?               e.g. `^song`, `^bpm=132`, `^l=8.`
+//   pause    := "," | "." | "?" | "!"             a rest inside a song, see below
+//   truncate := ("<" | ">") "." digits            e.g. `>.5`
+//
+// A `^song` (or `^s`) control opens a song section that runs until the next one, or the end of the vox.
+// Inside a song, runs of `.` are rests. Anything the tokenizer doesn't recognise is kept as text, so
+// every vox prints back exactly as it was written.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoxLangError {
+    // Byte offset into the vox
+    pub position: usize,
+    pub message: String,
+}
+impl fmt::Display for VoxLangError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result { write!(f, "at {}: {}", self.position, self.message) }
+}
+
+// A run of pitch modifiers like `+2`, `-3`, `+4-` or `++`, kept as written
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modifier(pub String);
+impl Modifier {
+    // Each signed number counts as written, a bare `+` or `-` nudges by one, so `+4-` is 3 and `++` is 2
+    pub fn semitones(&self) -> i32 {
+        let mut total = 0;
+        let mut sign = 1;
+        let mut digits = String::new();
+        let mut pending = false;
+        let mut flush = |sign:i32, digits:&mut String, pending:&mut bool| {
+            if *pending {
+                total += sign * digits.parse::<i32>().unwrap_or(1);
+            }
+            digits.clear();
+            *pending = false;
+        };
+        for c in self.0.chars() {
+            if c == '+' || c == '-' {
+                flush(sign, &mut digits, &mut pending);
+                sign = if c == '-' { -1 } else { 1 };
+            }
+            else {
+                digits.push(c);
+            }
+            pending = true;
+        }
+        flush(sign, &mut digits, &mut pending);
+        total
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Pitch {
+    pub prefix: Option<Modifier>,
+    pub suffix: Option<Modifier>,
+}
+impl Pitch {
+    pub fn semitones(&self) -> i32 { self.prefix.iter().chain(self.suffix.iter()).map(Modifier::semitones).sum() }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Control {
+    pub name: String,
+    pub arg: Option<String>,
+}
+impl Control {
+    pub fn is_song(&self) -> bool { self.name == "song" || self.name == "s" }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Word { name: String, pitch: Pitch },
+    Repeat { pitch: Pitch },
+    // A modifier with no word attached
+    Pitch(Modifier),
+    Control(Control),
+    Pause(char),
+    // `count` dots in a row inside a song
+    Rest { count: usize },
+    // `from_end` for `<`, the amount as written after the `.`
+    Truncate { from_end: bool, amount: String },
+    Song(SongSection),
+    Space(String),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongSection {
+    pub open: Control,
+    pub nodes: Vec<Node>,
+    // The `^song` that ended the section, if it didn't run to the end of the vox
+    pub close: Option<Control>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vox {
+    pub command: Option<String>,
+    pub nodes: Vec<Node>,
+}
+
+/////////////////////////////////////////////
+// Tokens
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Command(String),
+    Space(String),
+    Modifier(String),
+    Name(String),
+    Star,
+    Control(Control),
+    Pause(char),
+    Truncate { from_end: bool, amount: String },
+    Text(String),
+}
+
+const COMMANDS: &[&str] = &["!tc vox ", "!op vox "];
+
+fn is_name_start(c:char) -> bool { c.is_alphabetic() || c == '_' || c == '\'' }
+fn is_name_char(c:char) -> bool { c.is_alphanumeric() || c == '_' || c == '\'' }
+fn is_modifier_char(c:char) -> bool { c.is_ascii_digit() || c == '+' || c == '-' }
+
+// Splits `src` into tokens, each paired with its byte offset
+fn tokenize(src:&str) -> Result<Vec<(usize, Token)>, VoxLangError> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    if let Some(command) = COMMANDS.iter().find(|command| src.starts_with(*command)) {
+        tokens.push((0, Token::Command(command.to_string())));
+        pos = command.len();
+    }
+
+    let take_while = |from:usize, pred:&dyn Fn(char) -> bool| -> usize {
+        src[from..].char_indices().find(|&(_, c)| !pred(c)).map_or(src.len(), |(i, _)| from + i)
+    };
+
+    while let Some(c) = src[pos..].chars().next() {
+        let start = pos;
+        let token = if c.is_whitespace() {
+            pos = take_while(pos, &|c| c.is_whitespace());
+            Token::Space(src[start..pos].to_string())
+        }
+        else if is_modifier_char(c) {
+            pos = take_while(pos, &is_modifier_char);
+            Token::Modifier(src[start..pos].to_string())
+        }
+        else if is_name_start(c) {
+            pos = take_while(pos, &is_name_char);
+            Token::Name(src[start..pos].to_string())
+        }
+        else if c == '*' {
+            pos += 1;
+            Token::Star
+        }
+        else if c == '^' {
+            let name_end = take_while(pos + 1, &|c| c.is_ascii_alphanumeric() || c == '_');
+            if name_end == pos + 1 {
+                return Err(VoxLangError { position: start, message: "control code `^` has no name".to_string() });
+            }
+            let name = src[pos + 1..name_end].to_string();
+            pos = name_end;
+            let mut arg = None;
+            if src[pos..].starts_with('=') {
+                let arg_end = take_while(pos + 1, &|c| c.is_ascii_alphanumeric() || c == '.');
+                if arg_end == pos + 1 {
+                    return Err(VoxLangError { position: pos, message: format!("control code `^{name}=` has no value") });
+                }
+                arg = Some(src[pos + 1..arg_end].to_string());
+                pos = arg_end;
+            }
+            Token::Control(Control { name, arg })
+        }
+        else if (c == '<' || c == '>') && src[pos + 1..].starts_with('.') && src[pos + 2..].starts_with(|c:char| c.is_ascii_digit()) {
+            let amount_end = take_while(pos + 2, &|c| c.is_ascii_digit());
+            let amount = src[pos + 2..amount_end].to_string();
+            pos = amount_end;
+            Token::Truncate { from_end: c == '<', amount }
+        }
+        else if ",.?!".contains(c) {
+            pos += 1;
+            Token::Pause(c)
+        }
+        else {
+            pos += c.len_utf8();
+            Token::Text(c.to_string())
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+/////////////////////////////////////////////
+// Parsing
+
+// Numeric arguments that the vox engine needs to make sense of
+fn check_control(position:usize, control:&Control) -> Result<(), VoxLangError> {
+    let Some(arg) = &control.arg else { return Ok(()) };
+    let numeric = match control.name.as_str() {
+        "bpm" => arg.parse::<u32>().is_ok_and(|bpm| bpm > 0),
+        // Note lengths can be dotted, `^l=8.`
+        "l" => arg.trim_end_matches('.').parse::<u32>().is_ok_and(|len| len > 0),
+        _ => true,
+    };
+    if numeric {
+        Ok(())
+    }
+    else {
+        Err(VoxLangError { position, message: format!("`^{}` needs a positive number, got [{arg}]", control.name) })
+    }
+}
+
+// A name or `*` takes the modifier right after it
+fn take_suffix(tokens:&[(usize, Token)], i:&mut usize) -> Option<Modifier> {
+    match tokens.get(*i) {
+        Some((_, Token::Modifier(suffix))) => {
+            *i += 1;
+            Some(Modifier(suffix.clone()))
+        },
+        _ => None,
+    }
+}
+
+pub fn parse(src:&str) -> Result<Vox, VoxLangError> {
+    let tokens = tokenize(src)?;
+    let mut vox = Vox { command: None, nodes: Vec::new() };
+    let mut song : Option<SongSection> = None;
+    let mut i = 0;
+    while i < tokens.len() {
+        let (position, token) = &tokens[i];
+        i += 1;
+        let node = match token {
+            Token::Command(command) => {
+                vox.command = Some(command.clone());
+                continue;
+            },
+            Token::Space(space) => Node::Space(space.clone()),
+            Token::Name(name) => Node::Word { name: name.clone(), pitch: Pitch { prefix: None, suffix: take_suffix(&tokens, &mut i) } },
+            Token::Star => Node::Repeat { pitch: Pitch { prefix: None, suffix: take_suffix(&tokens, &mut i) } },
+            // Modifiers glue onto the name or `*` right after them
+            Token::Modifier(prefix) => match tokens.get(i) {
+                Some((_, Token::Name(name))) => {
+                    i += 1;
+                    let prefix = Some(Modifier(prefix.clone()));
+                    Node::Word { name: name.clone(), pitch: Pitch { prefix, suffix: take_suffix(&tokens, &mut i) } }
+                },
+                Some((_, Token::Star)) => {
+                    i += 1;
+                    let prefix = Some(Modifier(prefix.clone()));
+                    Node::Repeat { pitch: Pitch { prefix, suffix: take_suffix(&tokens, &mut i) } }
+                },
+                _ => Node::Pitch(Modifier(prefix.clone())),
+            },
+            Token::Control(control) => {
+                check_control(*position, control)?;
+                if control.is_song() {
+                    match song.take() {
+                        Some(mut open) => {
+                            open.close = Some(control.clone());
+                            vox.nodes.push(Node::Song(open));
+                        },
+                        None => song = Some(SongSection { open: control.clone(), nodes: Vec::new(), close: None }),
+                    }
+                    continue;
+                }
+                Node::Control(control.clone())
+            },
+            Token::Pause('.') if song.is_some() => {
+                let mut count = 1;
+                while let Some((_, Token::Pause('.'))) = tokens.get(i) {
+                    count += 1;
+                    i += 1;
+                }
+                Node::Rest { count }
+            },
+            Token::Pause(c) => Node::Pause(*c),
+            Token::Truncate { from_end, amount } => Node::Truncate { from_end: *from_end, amount: amount.clone() },
+            Token::Text(text) => Node::Text(text.clone()),
+        };
+        match song.as_mut() {
+            Some(open) => open.nodes.push(node),
+            None => vox.nodes.push(node),
+        }
+    }
+    if let Some(open) = song {
+        vox.nodes.push(Node::Song(open));
+    }
+    Ok(vox)
+}
+
+/////////////////////////////////////////////
+// Printing, back to the text it was parsed from
+
+impl fmt::Display for Modifier {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result { f.write_str(&self.0) }
+}
+
+impl fmt::Display for Control {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "^{}", self.name)?;
+        match &self.arg {
+            Some(arg) => write!(f, "={arg}"),
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_pitched(f:&mut fmt::Formatter, body:&str, pitch:&Pitch) -> fmt::Result {
+    if let Some(prefix) = &pitch.prefix {
+        write!(f, "{prefix}")?;
+    }
+    f.write_str(body)?;
+    if let Some(suffix) = &pitch.suffix {
+        write!(f, "{suffix}")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Node::Word { name, pitch } => write_pitched(f, name, pitch),
+            Node::Repeat { pitch } => write_pitched(f, "*", pitch),
+            Node::Pitch(modifier) => write!(f, "{modifier}"),
+            Node::Control(control) => write!(f, "{control}"),
+            Node::Pause(c) => write!(f, "{c}"),
+            Node::Rest { count } => f.write_str(&".".repeat(*count)),
+            Node::Truncate { from_end, amount } => write!(f, "{}.{amount}", if *from_end { '<' } else { '>' }),
+            Node::Song(song) => {
+                write!(f, "{}", song.open)?;
+                for node in &song.nodes {
+                    write!(f, "{node}")?;
+                }
+                match &song.close {
+                    Some(close) => write!(f, "{close}"),
+                    None => Ok(()),
+                }
+            },
+            Node::Space(text) | Node::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+impl fmt::Display for Vox {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        if let Some(command) = &self.command {
+            f.write_str(command)?;
+        }
+        for node in &self.nodes {
+            write!(f, "{node}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_parser;
+    use std::fs;
+
+    fn word(prefix:Option<&str>, name:&str, suffix:Option<&str>) -> Node {
+        Node::Word { name: name.to_string(), pitch: Pitch { prefix: prefix.map(|p| Modifier(p.to_string())), suffix: suffix.map(|s| Modifier(s.to_string())) } }
+    }
+
+    #[test]
+    fn every_stored_vox_round_trips() {
+        for entry in fs::read_dir("voxes").unwrap() {
+            let path = entry.unwrap().path();
+            for vox in log_parser::parse(&fs::read_to_string(&path).unwrap()).voxes {
+                let parsed = parse(&vox.body).unwrap_or_else(|e| panic!("[{}] line {} failed {e}", path.display(), vox.first_line));
+                assert_eq!(parsed.to_string(), vox.body);
+            }
+        }
+    }
+
+    #[test]
+    fn pitched_words_and_repeats() {
+        let vox = parse("-3kk4 n13-7 *+0 +4-* d1++ +8's+8n't").unwrap();
+        let nodes : Vec<&Node> = vox.nodes.iter().filter(|node| !matches!(node, Node::Space(_))).collect();
+        assert_eq!(nodes, [
+            &word(Some("-3"), "kk4", None),
+            &word(None, "n13", Some("-7")),
+            &Node::Repeat { pitch: Pitch { prefix: None, suffix: Some(Modifier("+0".to_string())) } },
+            &Node::Repeat { pitch: Pitch { prefix: Some(Modifier("+4-".to_string())), suffix: None } },
+            &word(None, "d1", Some("++")),
+            &word(Some("+8"), "'s", Some("+8")),
+            &word(None, "n't", None),
+        ]);
+    }
+
+    #[test]
+    fn modifiers_add_up_to_semitones() {
+        let semitones = |raw:&str| Modifier(raw.to_string()).semitones();
+        assert_eq!(semitones("+0"), 0);
+        assert_eq!(semitones("-3"), -3);
+        assert_eq!(semitones("10"), 10);
+        assert_eq!(semitones("+4-"), 3);
+        assert_eq!(semitones("++"), 2);
+        assert_eq!(semitones("-"), -1);
+        assert_eq!(Pitch { prefix: Some(Modifier("+8".to_string())), suffix: Some(Modifier("-2".to_string())) }.semitones(), 6);
+    }
+
+    #[test]
+    fn song_sections_hold_their_rests_and_controls() {
+        let vox = parse("happy! ^song ^bpm=170 ^l=8. n10 .. * ^song. done").unwrap();
+        let Node::Song(song) = &vox.nodes[3] else { panic!("expected a song, got {:?}", vox.nodes[3]) };
+        assert!(song.close.is_some());
+        assert!(song.nodes.contains(&Node::Control(Control { name: "l".to_string(), arg: Some("8.".to_string()) })));
+        assert!(song.nodes.contains(&Node::Rest { count: 2 }));
+        assert_eq!(vox.nodes[4], Node::Pause('.'));
+    }
+
+    #[test]
+    fn commands_and_truncation() {
+        let vox = parse("!tc vox hello>.5 world").unwrap();
+        assert_eq!(vox.command.as_deref(), Some("!tc vox "));
+        assert_eq!(vox.nodes[1], Node::Truncate { from_end: false, amount: "5".to_string() });
+        assert_eq!(vox.to_string(), "!tc vox hello>.5 world");
+    }
+
+    #[test]
+    fn malformed_control_codes_report_their_position() {
+        assert_eq!(parse("hello ^ world").unwrap_err().position, 6);
+        assert_eq!(parse("^song ^bpm= n1").unwrap_err().position, 10);
+        assert_eq!(parse("^song ^bpm=fast n1").unwrap_err().position, 6);
+        assert_eq!(parse("^l=0 n1").unwrap_err().position, 0);
+    }
+}
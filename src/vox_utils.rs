@@ -1,8 +1,5 @@
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::{self, BufRead};
 
 // Filters for strings sent to `vox_meta`
 lazy_static! { static ref COMMAND_RX: Regex = Regex::new(r"^!(tc|op) vox ").unwrap(); }
@@ -61,23 +58,17 @@ lazy_static! { static ref SHORTHAND_DICTIONARY : Vec<(Regex, &'static str)> = ve
 lazy_static! { static ref TOO_SHORT_RX : Regex = Regex::new(r"(^| )([a-zA-Z0-9_']{1,2})($|[\r\n\s ])").unwrap(); }
 lazy_static! { static ref CLEANUP_RX : Regex = Regex::new(r"( [ ]+)").unwrap(); }
 
-const VERBOSE : bool = false;
-
 pub mod filters {
-	use crate::vox_utils::VERBOSE;
-
-	fn print_if_verbose(step_name:&str, vox:&String) {
-		if VERBOSE {
-			println!("Step \"{step_name}\": [{vox}]");			
-		}
-
+	// What each step made of a vox, with `--log-level filters=trace`
+	fn trace_step(step_name:&str, vox:&str) {
+		tracing::trace!(target: "filters", step = step_name, "[{vox}]");
 	}
 
 	/////////////////////////////////////////////
 	// Filters for strings sent to `voxes`
 	pub fn sanatize(vox:String) -> String {
 		let output = vox.replace("\"", "").replace("‘", "'").replace("’", "'");
-		print_if_verbose("sanatize", &output);
+		trace_step("sanatize", &output);
 		output
 	}
 
@@ -87,7 +78,7 @@ pub mod filters {
 	pub fn commands(vox:String) -> String 
 	{
 		let output = COMMAND_RX.replace_all(&vox, "").to_string();
-		if VERBOSE { print_if_verbose("commands", &output); }
+		trace_step("commands", &output);
 		output
 	}
 
@@ -95,7 +86,7 @@ pub mod filters {
 	pub fn trunc(vox:String) -> String 
 	{
 		let output = TRUNC_RX.replace_all(&vox, "").to_string();
-		if VERBOSE { print_if_verbose("trunc", &output); }
+		trace_step("trunc", &output);
 		output
 	}
 
@@ -103,21 +94,21 @@ pub mod filters {
 	pub fn pause(vox:String) -> String 
 	{
 		let output = PAUSE_RX.replace_all(&vox, " ").to_string();
-		if VERBOSE { print_if_verbose("pause", &output); }
+		trace_step("pause", &output);
 		output
 	}
 
 	use crate::vox_utils::PITCH_RX;
 	pub fn pitch(vox:String) -> String { 
 		let output = PITCH_RX.replace_all(&vox, |caps: &regex::Captures| {caps[2].to_string()}).to_string();
-		if VERBOSE { print_if_verbose("pitch", &output); }
+		trace_step("pitch", &output);
 		output
 	}
 
 	use crate::vox_utils::CONTROL_CODES_RX;
 	pub fn control_codes(vox:String) -> String { 
 		let output = CONTROL_CODES_RX.replace_all(&vox, "").to_string();
-		if VERBOSE { print_if_verbose("control_codes", &output); }
+		trace_step("control_codes", &output);
 		output
 	}
 
@@ -125,7 +116,7 @@ pub mod filters {
 	pub fn contractions(vox:String) -> String { 
 		// No lookahead with Rust regex... ah well
 		let output = CONTRACTION_RX.replace_all(&vox, |caps: &regex::Captures| {format!(" {} ", &caps[1])}).to_string().replace("ca n't", "can't");
-		if VERBOSE { print_if_verbose("contractions", &output); }
+		trace_step("contractions", &output);
 		output
 	}
 
@@ -136,7 +127,7 @@ pub mod filters {
 		for entry in SHORTHAND_DICTIONARY.iter() {
 			ret_val = entry.0.replace_all(&ret_val, entry.1).to_string();
 		}
-		if VERBOSE { print_if_verbose("remap_note_shorthand", &ret_val); }
+		trace_step("remap_note_shorthand", &ret_val);
 		ret_val
 	}
 
@@ -149,39 +140,45 @@ pub mod filters {
 		while prev_output != output {
 			prev_output = output;			
 			output = TOO_SHORT_RX.replace_all(&prev_output, |caps: &regex::Captures| {format!("{}{:_<3}{}", &caps[1], &caps[2], &caps[3])}).to_string();
-			i = i + 1;
+			i += 1;
 			if i > 100 {
-				panic!("Timeout on pad_short_words: couldn't conclude on entry {}", vox);
+				tracing::warn!(vox = %vox, "Timeout on pad_short_words: couldn't conclude, using it as is");
+				break;
 			}
 		}
-		if VERBOSE { print_if_verbose("pad_short_words", &output); }
+		trace_step("pad_short_words", &output);
 		output
 	}
 
 	use crate::vox_utils::CLEANUP_RX;
 	pub fn cleanup(vox:String) -> String { 
 		let output = CLEANUP_RX.replace_all(&vox, " ").to_string();
-		if VERBOSE { print_if_verbose("cleanup", &output); }
+		trace_step("cleanup", &output);
 		output
 	}
-}
 
-lazy_static! { static ref VOX_DB : HashSet<String> = {
-	let mut val = HashSet::new();
-	let file = match File::open("vox_db.txt")  {
-		Err(e) => panic!("Opening vox_db.txt failed: {:?}", e),
-		Ok(file) => file,
-	};
-	let lines = io::BufReader::new(file).lines();
-	for line in lines {
-		if let Ok(prim) = line {
-			val.insert(prim);
-		}
+	// Every filter in the order `index_log` runs them, so searches match what was indexed
+	pub fn normalize(vox:&str) -> String {
+		cleanup(
+		pad_short_words(
+		remap_note_shorthand(
+		contractions(
+		control_codes(
+		pitch(
+		pause(
+		trunc(
+		commands(
+		sanatize( vox.to_lowercase() ))))))))))
 	}
-	val
-};}
 
-pub mod validators {
-	use crate::vox_utils::VOX_DB;
-	pub fn valid(word:&str) -> bool { VOX_DB.contains(word) }
-}
\ No newline at end of file
+	// The distinct words of a search query, as `index_log` would have indexed them
+	pub fn search_terms(query:&str) -> Vec<String> {
+		let mut terms : Vec<String> = Vec::new();
+		for word in normalize(query).split_whitespace() {
+			if !terms.iter().any(|term| term == word) {
+				terms.push(word.to_string());
+			}
+		}
+		terms
+	}
+}